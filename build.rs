@@ -0,0 +1,31 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Bakes the git commit into the binary for `/version`. `GIT_COMMIT` wins if it's set, e.g. for
+/// builds outside a checkout; otherwise it's "unknown" when git can't tell. Only the server
+/// binary reads it, so library builds skip asking git.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if std::env::var_os("CARGO_FEATURE_SERVER").is_none() {
+        return;
+    }
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+                .map(|commit| commit.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={commit}");
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    // a missing path would make cargo rerun this on every build, e.g. from a crates.io tarball
+    if Path::new(".git").exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        println!("cargo:rerun-if-changed=.git/refs/heads");
+    }
+}
@@ -0,0 +1,405 @@
+use anyhow::{anyhow, Result};
+use banyan_shared::types::BlockNum;
+use ethers::types::{H256, U64};
+use lru::LruCache;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// A block the node didn't return a hash for, as opposed to the whole request failing.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Block {block_num}: {reason}")]
+pub struct BlockHashError {
+    pub block_num: u64,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    id: u64,
+    result: Option<T>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct BlockHeader {
+    hash: H256,
+    number: U64,
+    timestamp: U64,
+}
+
+/// Fetches block hashes with JSON-RPC batch requests, and remembers the ones too deep to be
+/// reorged. Shared by every validation in the process, since overlapping deals keep asking for
+/// the same blocks. Also answers the other block-level questions the validator asks the node.
+pub struct BlockHashFetcher {
+    http: reqwest::Client,
+    rpc_url: String,
+    /// Most `eth_getBlockByNumber` calls sent in one batch.
+    batch_size: usize,
+    finalized: Mutex<LruCache<u64, H256>>,
+    /// How many blocks a block has to be buried under before its hash is cached.
+    confirmation_depth: u64,
+}
+
+impl BlockHashFetcher {
+    pub fn new(
+        rpc_url: String,
+        batch_size: usize,
+        cache_size: usize,
+        confirmation_depth: u64,
+    ) -> Result<Self> {
+        let cache_size = NonZeroUsize::new(cache_size)
+            .ok_or_else(|| anyhow!("Block hash cache size must be > 0"))?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            rpc_url,
+            batch_size: batch_size.max(1),
+            finalized: Mutex::new(LruCache::new(cache_size)),
+            confirmation_depth,
+        })
+    }
+
+    /// Makes a single JSON-RPC call. Errors if the node returns an error or a null result.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T> {
+        let call = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": method,
+            "params": params,
+        });
+        let response: RpcResponse<T> = self
+            .http
+            .post(&self.rpc_url)
+            .json(&call)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match (response.result, response.error) {
+            (Some(result), _) => Ok(result),
+            (None, Some(error)) => Err(anyhow!("{method}: {error}")),
+            (None, None) => Err(anyhow!("{method}: no result")),
+        }
+    }
+
+    pub async fn get_chain_id(&self) -> Result<u64> {
+        let chain_id: U64 = self.call("eth_chainId", serde_json::json!([])).await?;
+        Ok(chain_id.as_u64())
+    }
+
+    pub async fn get_latest_block_num(&self) -> Result<BlockNum> {
+        let block_num: U64 = self.call("eth_blockNumber", serde_json::json!([])).await?;
+        Ok(BlockNum(block_num.as_u64()))
+    }
+
+    /// Number and unix timestamp of the node's latest block.
+    pub async fn get_latest_block(&self) -> Result<(BlockNum, u64)> {
+        let header: BlockHeader = self
+            .call("eth_getBlockByNumber", serde_json::json!(["latest", false]))
+            .await?;
+        Ok((BlockNum(header.number.as_u64()), header.timestamp.as_u64()))
+    }
+
+    /// Number of the block the node reports under the `finalized` tag.
+    pub async fn get_finalized_block_num(&self) -> Result<BlockNum> {
+        let header: BlockHeader = self
+            .call(
+                "eth_getBlockByNumber",
+                serde_json::json!(["finalized", false]),
+            )
+            .await?;
+        Ok(BlockNum(header.number.as_u64()))
+    }
+
+    /// Hash of a single block, bypassing the cache.
+    pub async fn get_block_hash(&self, block_num: BlockNum) -> Result<H256> {
+        let header: BlockHeader = self
+            .call(
+                "eth_getBlockByNumber",
+                serde_json::json!([format!("{:#x}", block_num.0), false]),
+            )
+            .await?;
+        Ok(header.hash)
+    }
+
+    /// Sends one batch of `eth_getBlockByNumber` calls, using the block number as the call id.
+    /// Responses may come back in any order and are matched up by id.
+    async fn fetch_batch(&self, block_nums: &[u64]) -> Result<Vec<(u64, H256)>> {
+        let calls: Vec<serde_json::Value> = block_nums
+            .iter()
+            .map(|block_num| {
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": block_num,
+                    "method": "eth_getBlockByNumber",
+                    "params": [format!("{block_num:#x}"), false],
+                })
+            })
+            .collect();
+        let responses: Vec<RpcResponse<BlockHeader>> = self
+            .http
+            .post(&self.rpc_url)
+            .json(&calls)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        responses
+            .into_iter()
+            .map(|response| match (response.result, response.error) {
+                (Some(header), _) => Ok((response.id, header.hash)),
+                (None, Some(error)) => Err(BlockHashError {
+                    block_num: response.id,
+                    reason: error.to_string(),
+                }
+                .into()),
+                (None, None) => Err(BlockHashError {
+                    block_num: response.id,
+                    reason: "not found".to_string(),
+                }
+                .into()),
+            })
+            .collect()
+    }
+
+    /// Hashes of `block_nums`, keyed by block number. Blocks at least `confirmation_depth`
+    /// below `latest_block_num` are served from and added to the cache.
+    pub async fn get_block_hashes(
+        &self,
+        block_nums: &[BlockNum],
+        latest_block_num: BlockNum,
+    ) -> Result<HashMap<u64, H256>> {
+        let mut hashes = HashMap::new();
+        let mut missing = Vec::new();
+        {
+            let mut finalized = self
+                .finalized
+                .lock()
+                .expect("block hash cache lock poisoned");
+            for block_num in block_nums {
+                match finalized.get(&block_num.0) {
+                    Some(hash) => {
+                        hashes.insert(block_num.0, *hash);
+                    }
+                    None => missing.push(block_num.0),
+                }
+            }
+        }
+        missing.sort_unstable();
+        missing.dedup();
+
+        for batch in missing.chunks(self.batch_size) {
+            let fetched = self.fetch_batch(batch).await?;
+            let mut finalized = self
+                .finalized
+                .lock()
+                .expect("block hash cache lock poisoned");
+            for (block_num, hash) in fetched {
+                if block_num + self.confirmation_depth <= latest_block_num.0 {
+                    finalized.put(block_num, hash);
+                }
+                hashes.insert(block_num, hash);
+            }
+        }
+        if let Some(block_num) = block_nums.iter().find(|b| !hashes.contains_key(&b.0)) {
+            return Err(BlockHashError {
+                block_num: block_num.0,
+                reason: "no hash returned".to_string(),
+            }
+            .into());
+        }
+        Ok(hashes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    type Answer = Arc<dyn Fn(&Value) -> Value + Send + Sync>;
+
+    /// A JSON-RPC endpoint on localhost that answers each POSTed body with `answer` and records
+    /// what it was sent.
+    struct StubRpc {
+        url: String,
+        requests: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl StubRpc {
+        fn start(answer: impl Fn(&Value) -> Value + Send + Sync + 'static) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("bind stub RPC");
+            let url = format!("http://{}", listener.local_addr().expect("stub address"));
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let answer: Answer = Arc::new(answer);
+            let recorded = requests.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let (answer, recorded) = (answer.clone(), recorded.clone());
+                    thread::spawn(move || serve(stream, answer, recorded));
+                }
+            });
+            Self { url, requests }
+        }
+
+        fn requests(&self) -> Vec<Value> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    /// Answers every request on one keep-alive connection until the client hangs up.
+    fn serve(
+        stream: TcpStream,
+        answer: Answer,
+        recorded: Arc<Mutex<Vec<Value>>>,
+    ) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let mut content_length = 0;
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            let request: Value = serde_json::from_slice(&body)?;
+            let response = serde_json::to_vec(&answer(&request))?;
+            recorded.lock().unwrap().push(request);
+            write!(
+                writer,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                response.len()
+            )?;
+            writer.write_all(&response)?;
+        }
+    }
+
+    fn hash(block_num: u64) -> H256 {
+        H256::from_low_u64_be(block_num)
+    }
+
+    /// Answers a batch with a header per call, in reverse order, with null for `missing`.
+    fn headers_except(missing: Option<u64>) -> impl Fn(&Value) -> Value {
+        move |batch| {
+            let mut responses: Vec<Value> = batch
+                .as_array()
+                .expect("a batch request")
+                .iter()
+                .map(|call| {
+                    let block_num = call["id"].as_u64().expect("block number id");
+                    let result = if Some(block_num) == missing {
+                        Value::Null
+                    } else {
+                        json!({
+                            "hash": hash(block_num),
+                            "number": format!("{block_num:#x}"),
+                            "timestamp": "0x0",
+                        })
+                    };
+                    json!({"jsonrpc": "2.0", "id": block_num, "result": result})
+                })
+                .collect();
+            responses.reverse();
+            Value::Array(responses)
+        }
+    }
+
+    fn batch_ids(batch: &Value) -> Vec<u64> {
+        batch
+            .as_array()
+            .expect("a batch request")
+            .iter()
+            .map(|call| call["id"].as_u64().expect("block number id"))
+            .collect()
+    }
+
+    #[tokio::test]
+    /// Blocks are deduplicated, split into batches of `batch_size`, and matched up by id
+    /// whatever order the node answers in.
+    async fn batches_are_split_and_matched_by_id() {
+        let rpc = StubRpc::start(headers_except(None));
+        let fetcher = BlockHashFetcher::new(rpc.url.clone(), 2, 16, 12).unwrap();
+
+        let block_nums = [BlockNum(5), BlockNum(3), BlockNum(4), BlockNum(3)];
+        let hashes = fetcher
+            .get_block_hashes(&block_nums, BlockNum(100))
+            .await
+            .unwrap();
+        assert_eq!(
+            hashes,
+            HashMap::from([(3, hash(3)), (4, hash(4)), (5, hash(5))])
+        );
+
+        let requests = rpc.requests();
+        assert_eq!(
+            requests.iter().map(batch_ids).collect::<Vec<_>>(),
+            vec![vec![3, 4], vec![5]]
+        );
+        assert_eq!(requests[0][1]["method"], "eth_getBlockByNumber");
+        assert_eq!(requests[0][1]["params"], json!(["0x4", false]));
+    }
+
+    #[tokio::test]
+    /// A null result fails the lookup and names the block it was for.
+    async fn null_result_names_the_block() {
+        let rpc = StubRpc::start(headers_except(Some(4)));
+        let fetcher = BlockHashFetcher::new(rpc.url.clone(), 8, 16, 12).unwrap();
+
+        let error = fetcher
+            .get_block_hashes(&[BlockNum(3), BlockNum(4), BlockNum(5)], BlockNum(100))
+            .await
+            .expect_err("block 4 has no header");
+        let error = error
+            .downcast::<BlockHashError>()
+            .expect("a per-block error");
+        assert_eq!(error.block_num, 4);
+    }
+
+    #[tokio::test]
+    /// Only hashes at least `confirmation_depth` below the latest block are cached.
+    async fn only_final_hashes_are_cached() {
+        let rpc = StubRpc::start(headers_except(None));
+        let fetcher = BlockHashFetcher::new(rpc.url.clone(), 8, 16, 12).unwrap();
+        let block_nums = [BlockNum(8), BlockNum(9)];
+
+        // 8 is 12 blocks deep, 9 only 11
+        fetcher
+            .get_block_hashes(&block_nums, BlockNum(20))
+            .await
+            .unwrap();
+        let hashes = fetcher
+            .get_block_hashes(&block_nums, BlockNum(20))
+            .await
+            .unwrap();
+        assert_eq!(hashes, HashMap::from([(8, hash(8)), (9, hash(9))]));
+        assert_eq!(
+            rpc.requests().iter().map(batch_ids).collect::<Vec<_>>(),
+            vec![vec![8, 9], vec![9]]
+        );
+    }
+}
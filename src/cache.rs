@@ -0,0 +1,128 @@
+use crate::chain::DealInfo;
+use crate::validate::ChainlinkResponse;
+use anyhow::{anyhow, Result};
+use banyan_shared::types::{BlockNum, DealID};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::Mutex;
+#[cfg(feature = "persistence")]
+use tracing::warn;
+
+/// Everything a validation result depends on besides the chain itself.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub deal_id: u64,
+    pub deal_start_block: u64,
+    pub deal_length_in_blocks: u64,
+    pub proof_frequency_in_blocks: u64,
+    pub blake3_checksum: String,
+}
+
+impl CacheKey {
+    pub fn new(deal_id: DealID, deal_info: &DealInfo) -> Self {
+        Self {
+            deal_id: deal_id.0,
+            deal_start_block: deal_info.deal_start_block.0,
+            deal_length_in_blocks: deal_info.deal_length_in_blocks.0,
+            proof_frequency_in_blocks: deal_info.proof_frequency_in_blocks.0,
+            blake3_checksum: deal_info.blake3_checksum.to_hex().to_string(),
+        }
+    }
+}
+
+/// Results of deals that are over and past finality, which can never change.
+pub struct ResultCache {
+    entries: Mutex<LruCache<CacheKey, ChainlinkResponse>>,
+    /// Optional on-disk copy, so finalized results survive a restart.
+    #[cfg(feature = "persistence")]
+    disk: Option<sled::Tree>,
+    /// How many blocks the last proof window has to be buried under before a result is cached.
+    confirmation_depth: u64,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize, confirmation_depth: u64, path: Option<&Path>) -> Result<Self> {
+        let capacity =
+            NonZeroUsize::new(capacity).ok_or_else(|| anyhow!("Cache capacity must be > 0"))?;
+        #[cfg(feature = "persistence")]
+        let disk = match path {
+            Some(path) => Some(
+                sled::open(path)
+                    .map_err(|e| anyhow!("Couldn't open result cache at {:?}: {e}", path))?
+                    .open_tree("results")?,
+            ),
+            None => None,
+        };
+        #[cfg(not(feature = "persistence"))]
+        if let Some(path) = path {
+            return Err(anyhow!(
+                "Can't keep results at {:?} without the persistence feature",
+                path
+            ));
+        }
+        Ok(Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            #[cfg(feature = "persistence")]
+            disk,
+            confirmation_depth,
+        })
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<ChainlinkResponse> {
+        let mut entries = self.entries.lock().expect("result cache lock poisoned");
+        if let Some(response) = entries.get(key) {
+            return Some(response.clone());
+        }
+        let response = self.read_disk(key)?;
+        entries.put(key.clone(), response.clone());
+        Some(response)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn read_disk(&self, key: &CacheKey) -> Option<ChainlinkResponse> {
+        let bytes = self
+            .disk
+            .as_ref()?
+            .get(serde_json::to_vec(key).ok()?)
+            .ok()??;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    #[cfg(not(feature = "persistence"))]
+    fn read_disk(&self, _key: &CacheKey) -> Option<ChainlinkResponse> {
+        None
+    }
+
+    #[cfg(feature = "persistence")]
+    fn persist(disk: &sled::Tree, key: &CacheKey, response: &ChainlinkResponse) -> Result<()> {
+        disk.insert(serde_json::to_vec(key)?, serde_json::to_vec(response)?)?;
+        Ok(())
+    }
+
+    /// Caches `response` if the deal's last proof window, ending at `last_window_end`, is at least
+    /// `confirmation_depth` blocks below `current_block_num`. Returns whether it was cached.
+    pub fn insert_if_final(
+        &self,
+        key: CacheKey,
+        last_window_end: BlockNum,
+        current_block_num: BlockNum,
+        response: &ChainlinkResponse,
+    ) -> bool {
+        if current_block_num.0 < last_window_end.0 + self.confirmation_depth {
+            return false;
+        }
+        #[cfg(feature = "persistence")]
+        if let Some(disk) = &self.disk {
+            if let Err(e) = Self::persist(disk, &key, response) {
+                warn!("Couldn't persist result for deal {}: {e}", key.deal_id);
+            }
+        }
+        self.entries
+            .lock()
+            .expect("result cache lock poisoned")
+            .put(key, response.clone());
+        true
+    }
+}
@@ -0,0 +1,268 @@
+#[cfg(feature = "metrics")]
+use crate::metrics::METRICS;
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+/// Delivers async results back to the Chainlink node by PATCHing the request's `responseURL`.
+pub struct CallbackClient {
+    http: reqwest::Client,
+    /// The bridge's incoming token, sent as a bearer token because the node checks it on
+    /// `/v2/resume` before accepting the result.
+    incoming_token: Option<String>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+/// Why a delivery attempt failed, which decides whether it's worth trying again.
+enum DeliveryError {
+    /// The node couldn't be reached, had a server error, or asked to be tried again later.
+    Retryable(anyhow::Error),
+    /// The node rejected the result (a 4xx, e.g. a wrong token or an unknown run), so sending
+    /// it again can't help.
+    Rejected(anyhow::Error),
+}
+
+impl CallbackClient {
+    pub fn new(
+        incoming_token: Option<String>,
+        max_attempts: u32,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            incoming_token,
+            max_attempts: max_attempts.max(1),
+            initial_backoff,
+            max_backoff: max_backoff.max(initial_backoff),
+        }
+    }
+
+    fn next_backoff(&self, backoff: Duration) -> Duration {
+        (backoff * 2).min(self.max_backoff)
+    }
+
+    async fn try_deliver(
+        &self,
+        response_url: &str,
+        body: &serde_json::Value,
+    ) -> Result<(), DeliveryError> {
+        let mut request = self.http.patch(response_url).json(body);
+        if let Some(token) = &self.incoming_token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Retryable(e.into()))?;
+        let status = response.status();
+        // a timeout or rate limit is worth waiting out, unlike the rest of the 4xxs
+        let retry_later =
+            status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS;
+        if status.is_client_error() && !retry_later {
+            return Err(DeliveryError::Rejected(anyhow!(
+                "Chainlink node rejected the result with {status}"
+            )));
+        }
+        if !status.is_success() {
+            return Err(DeliveryError::Retryable(anyhow!(
+                "Chainlink node answered {status}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// PATCHes `body` to `response_url`, retrying with exponential backoff (up to
+    /// `max_backoff` between attempts) unless the node rejects it outright. Never panics; the final outcome is logged and returned.
+    pub async fn deliver(
+        &self,
+        job_run_id: &str,
+        response_url: &str,
+        body: &serde_json::Value,
+    ) -> Result<()> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.try_deliver(response_url, body).await {
+                Ok(()) => {
+                    info!("Delivered result for job {job_run_id} on attempt {attempt}");
+                    return Ok(());
+                }
+                Err(DeliveryError::Retryable(e)) if attempt < self.max_attempts => {
+                    warn!(
+                        "Attempt {attempt} to deliver result for job {job_run_id} failed, retrying in {}ms: {e}",
+                        backoff.as_millis()
+                    );
+                    sleep(backoff).await;
+                    backoff = self.next_backoff(backoff);
+                    attempt += 1;
+                }
+                Err(DeliveryError::Retryable(e) | DeliveryError::Rejected(e)) => {
+                    #[cfg(feature = "metrics")]
+                    METRICS.callback_failures.inc();
+                    error!(
+                        "Giving up delivering result for job {job_run_id} after {attempt} attempts: {e}"
+                    );
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// A Chainlink node's `/v2/resume` on localhost that answers the n-th PATCH with the n-th
+    /// of `statuses` (the last one from then on) and records each request's Authorization header.
+    struct StubNode {
+        url: String,
+        authorizations: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    impl StubNode {
+        fn start(statuses: &[u16]) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("bind stub node");
+            let url = format!(
+                "http://{}/v2/resume/1",
+                listener.local_addr().expect("stub address")
+            );
+            let authorizations = Arc::new(Mutex::new(Vec::new()));
+            let (statuses, recorded) = (statuses.to_vec(), authorizations.clone());
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let (statuses, recorded) = (statuses.clone(), recorded.clone());
+                    thread::spawn(move || serve(stream, &statuses, recorded));
+                }
+            });
+            Self {
+                url,
+                authorizations,
+            }
+        }
+
+        fn authorizations(&self) -> Vec<Option<String>> {
+            self.authorizations.lock().unwrap().clone()
+        }
+    }
+
+    /// Answers every request on one keep-alive connection until the client hangs up.
+    fn serve(
+        stream: TcpStream,
+        statuses: &[u16],
+        recorded: Arc<Mutex<Vec<Option<String>>>>,
+    ) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let (mut content_length, mut authorization) = (0, None);
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap_or(0);
+                    } else if name.eq_ignore_ascii_case("authorization") {
+                        authorization = Some(value.trim().to_string());
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            let status = {
+                let mut recorded = recorded.lock().unwrap();
+                recorded.push(authorization);
+                statuses[(recorded.len() - 1).min(statuses.len() - 1)]
+            };
+            write!(
+                writer,
+                "HTTP/1.1 {status} Stub\r\nContent-Length: 0\r\n\r\n"
+            )?;
+        }
+    }
+
+    fn client(max_attempts: u32) -> CallbackClient {
+        CallbackClient::new(
+            Some("incoming".to_string()),
+            max_attempts,
+            Duration::from_millis(1),
+            Duration::from_millis(4),
+        )
+    }
+
+    #[tokio::test]
+    /// Server errors are retried until the node takes the result, which carries the incoming token.
+    async fn server_errors_are_retried() {
+        let node = StubNode::start(&[500, 503, 200]);
+        client(5)
+            .deliver("1", &node.url, &json!({"data": {}}))
+            .await
+            .expect("delivered on the third attempt");
+        assert_eq!(
+            node.authorizations(),
+            vec![Some("Bearer incoming".to_string()); 3]
+        );
+    }
+
+    #[tokio::test]
+    /// A 4xx won't change on a resend, so it's given up on at once.
+    async fn rejections_are_not_retried() {
+        let node = StubNode::start(&[401, 200]);
+        client(5)
+            .deliver("1", &node.url, &json!({"data": {}}))
+            .await
+            .expect_err("rejected");
+        assert_eq!(node.authorizations().len(), 1);
+    }
+
+    #[tokio::test]
+    /// 408 and 429 ask for the result again later, so they're retried like server errors.
+    async fn timeouts_and_rate_limits_are_retried() {
+        let node = StubNode::start(&[408, 429, 200]);
+        client(5)
+            .deliver("1", &node.url, &json!({"data": {}}))
+            .await
+            .expect("delivered on the third attempt");
+        assert_eq!(node.authorizations().len(), 3);
+    }
+
+    #[tokio::test]
+    /// Retrying stops after `max_attempts`.
+    async fn retries_give_up_after_max_attempts() {
+        let node = StubNode::start(&[503]);
+        client(3)
+            .deliver("1", &node.url, &json!({"data": {}}))
+            .await
+            .expect_err("never delivered");
+        assert_eq!(node.authorizations().len(), 3);
+    }
+
+    #[test]
+    /// The backoff doubles, but never past `max_backoff`.
+    fn backoff_is_capped() {
+        let client = client(5);
+        let backoffs: Vec<u128> = std::iter::successors(Some(client.initial_backoff), |backoff| {
+            Some(client.next_backoff(*backoff))
+        })
+        .take(5)
+        .map(|backoff| backoff.as_millis())
+        .collect();
+        assert_eq!(backoffs, vec![1, 2, 4, 4, 4]);
+    }
+}
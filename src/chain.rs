@@ -0,0 +1,501 @@
+use crate::blocks::{BlockHashError, BlockHashFetcher};
+use crate::contract::{Deal, ProofsContract};
+#[cfg(feature = "metrics")]
+use crate::metrics::METRICS;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use banyan_shared::types::{BlockNum, DealID};
+use ethers::types::{H256, U256};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use thiserror::Error;
+
+/// The parts of an on-chain deal that validation depends on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DealInfo {
+    pub deal_start_block: BlockNum,
+    pub deal_length_in_blocks: BlockNum,
+    pub proof_frequency_in_blocks: BlockNum,
+    pub file_size: u64,
+    pub blake3_checksum: blake3::Hash,
+}
+
+impl DealInfo {
+    /// None if the end is past the last block, which anyone can set up since `startOffer` is
+    /// permissionless.
+    pub fn deal_end_block(&self) -> Option<BlockNum> {
+        self.deal_start_block
+            .0
+            .checked_add(self.deal_length_in_blocks.0)
+            .map(BlockNum)
+    }
+
+    pub fn deal_over(&self, current_block_num: BlockNum) -> bool {
+        self.deal_end_block().map_or(false, |deal_end_block| {
+            current_block_num.0 >= deal_end_block.0
+        })
+    }
+}
+
+/// A deal whose on-chain parameters can't be validated, e.g. because they don't fit in a u64.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{0}")]
+pub struct InvalidDeal(pub String);
+
+/// The `DealInfo` for a deal as the contract returns it. None for an unknown deal id, which
+/// reads back as all zeroes.
+pub(crate) fn deal_info_from_contract(deal: &Deal) -> Result<Option<DealInfo>, InvalidDeal> {
+    if deal.deal_start_block.is_zero() {
+        return Ok(None);
+    }
+    let to_u64 = |name: &str, value: U256| {
+        u64::try_from(value).map_err(|_| InvalidDeal(format!("{name} {value} is too large")))
+    };
+    Ok(Some(DealInfo {
+        deal_start_block: BlockNum(to_u64("dealStartBlock", deal.deal_start_block)?),
+        deal_length_in_blocks: BlockNum(to_u64("dealLengthInBlocks", deal.deal_length_in_blocks)?),
+        proof_frequency_in_blocks: BlockNum(to_u64(
+            "proofFrequencyInBlocks",
+            deal.proof_frequency_in_blocks,
+        )?),
+        file_size: to_u64("fileSize", deal.file_size)?,
+        blake3_checksum: parse_blake3_checksum(&deal.blake3_checksum)
+            .map_err(|e| InvalidDeal(e.to_string()))?,
+    }))
+}
+
+/// Parses the `blake3Checksum` string the contract stores: the 64 hex digits of
+/// `blake3::Hash::to_hex`, as the uploader passed them to `startOffer`, with or without `0x`.
+pub(crate) fn parse_blake3_checksum(checksum: &str) -> Result<blake3::Hash> {
+    let hex = checksum.strip_prefix("0x").unwrap_or(checksum);
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(anyhow!("Invalid blake3 checksum {checksum:?}"));
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .map_err(|e| anyhow!("Invalid blake3 checksum {checksum:?}: {e}"))?;
+    }
+    Ok(blake3::Hash::from(bytes))
+}
+
+/// Every chain read the validator makes. `EthChain` serves these from Goerli, `MockChain` from memory.
+#[async_trait]
+pub trait DealChain: Send + Sync {
+    /// The deal as of `block_num`. None if no deal with this id had been created by then. A deal
+    /// that exists but can't be validated is an `InvalidDeal` error.
+    async fn get_offer(&self, deal_id: DealID, block_num: BlockNum) -> Result<Option<DealInfo>>;
+
+    async fn get_latest_block_num(&self) -> Result<BlockNum>;
+
+    /// Latest block the node considers finalized, for the `finalized` block tag.
+    async fn get_finalized_block_num(&self) -> Result<BlockNum>;
+
+    async fn get_block_hash_from_num(&self, block_num: BlockNum) -> Result<H256>;
+
+    /// Hashes of all of `block_nums` at once, keyed by block number. `latest_block_num` tells
+    /// implementations that cache which blocks are deep enough to be final. A failure that is
+    /// down to one block is a `BlockHashError` naming it.
+    async fn get_block_hashes(
+        &self,
+        block_nums: &[BlockNum],
+        _latest_block_num: BlockNum,
+    ) -> Result<HashMap<u64, H256>> {
+        let mut hashes = HashMap::new();
+        for block_num in block_nums {
+            let hash = self
+                .get_block_hash_from_num(*block_num)
+                .await
+                .map_err(|e| BlockHashError {
+                    block_num: block_num.0,
+                    reason: e.to_string(),
+                })?;
+            hashes.insert(block_num.0, hash);
+        }
+        Ok(hashes)
+    }
+
+    /// Block the proof for `window_num` landed in, as recorded at `block_num`.
+    async fn get_proof_block_num_from_window(
+        &self,
+        deal_id: DealID,
+        window_num: u64,
+        block_num: BlockNum,
+    ) -> Result<Option<BlockNum>>;
+
+    async fn get_proof_from_logs(
+        &self,
+        block_num: BlockNum,
+        deal_id: DealID,
+    ) -> Result<Option<Vec<u8>>>;
+
+    /// Every proof logged for the deal between `from_block` and `to_block` inclusive, keyed by
+    /// the block it landed in. One range scan instead of a `get_proof_from_logs` per window.
+    async fn get_proofs_in_range(
+        &self,
+        deal_id: DealID,
+        from_block: BlockNum,
+        to_block: BlockNum,
+    ) -> Result<HashMap<u64, Vec<u8>>>;
+
+    /// First block at which the deal counts as cancelled, if it is cancelled as of `latest_block_num`.
+    async fn get_cancellation_block(
+        &self,
+        deal_id: DealID,
+        deal_start_block: BlockNum,
+        latest_block_num: BlockNum,
+    ) -> Result<Option<BlockNum>>;
+}
+
+pub struct EthChain {
+    pub contract: ProofsContract,
+    pub block_hashes: BlockHashFetcher,
+}
+
+/// Runs a chain read, timed under `method`.
+#[cfg(feature = "metrics")]
+async fn timed<T>(method: &'static str, call: impl Future<Output = Result<T>>) -> Result<T> {
+    METRICS.time_rpc(method, call).await
+}
+
+/// Runs a chain read. Without the `metrics` feature there's nothing to record it in.
+#[cfg(not(feature = "metrics"))]
+async fn timed<T>(_method: &'static str, call: impl Future<Output = Result<T>>) -> Result<T> {
+    call.await
+}
+
+#[async_trait]
+impl DealChain for EthChain {
+    async fn get_offer(&self, deal_id: DealID, block_num: BlockNum) -> Result<Option<DealInfo>> {
+        let deal = timed("get_offer", self.contract.get_deal(deal_id, block_num)).await?;
+        Ok(deal_info_from_contract(&deal)?)
+    }
+
+    async fn get_latest_block_num(&self) -> Result<BlockNum> {
+        timed(
+            "get_latest_block_num",
+            self.block_hashes.get_latest_block_num(),
+        )
+        .await
+    }
+
+    async fn get_finalized_block_num(&self) -> Result<BlockNum> {
+        timed(
+            "get_finalized_block_num",
+            self.block_hashes.get_finalized_block_num(),
+        )
+        .await
+    }
+
+    async fn get_block_hash_from_num(&self, block_num: BlockNum) -> Result<H256> {
+        timed(
+            "get_block_hash_from_num",
+            self.block_hashes.get_block_hash(block_num),
+        )
+        .await
+    }
+
+    async fn get_block_hashes(
+        &self,
+        block_nums: &[BlockNum],
+        latest_block_num: BlockNum,
+    ) -> Result<HashMap<u64, H256>> {
+        timed(
+            "get_block_hashes",
+            self.block_hashes
+                .get_block_hashes(block_nums, latest_block_num),
+        )
+        .await
+    }
+
+    async fn get_proof_block_num_from_window(
+        &self,
+        deal_id: DealID,
+        window_num: u64,
+        block_num: BlockNum,
+    ) -> Result<Option<BlockNum>> {
+        timed(
+            "get_proof_block_num_from_window",
+            self.contract
+                .get_proof_block(deal_id, window_num, block_num),
+        )
+        .await
+    }
+
+    async fn get_proof_from_logs(
+        &self,
+        block_num: BlockNum,
+        deal_id: DealID,
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(timed(
+            "get_proof_from_logs",
+            self.contract
+                .get_proofs_in_range(deal_id, block_num, block_num),
+        )
+        .await?
+        .remove(&block_num.0))
+    }
+
+    async fn get_proofs_in_range(
+        &self,
+        deal_id: DealID,
+        from_block: BlockNum,
+        to_block: BlockNum,
+    ) -> Result<HashMap<u64, Vec<u8>>> {
+        timed(
+            "get_proofs_in_range",
+            self.contract
+                .get_proofs_in_range(deal_id, from_block, to_block),
+        )
+        .await
+    }
+
+    async fn get_cancellation_block(
+        &self,
+        deal_id: DealID,
+        deal_start_block: BlockNum,
+        latest_block_num: BlockNum,
+    ) -> Result<Option<BlockNum>> {
+        timed(
+            "get_cancellation_block",
+            self.contract
+                .get_cancellation_block(deal_id, deal_start_block, latest_block_num),
+        )
+        .await
+    }
+}
+
+/// In-memory chain for exercising validation without an RPC provider.
+#[derive(Debug, Clone, Default)]
+pub struct MockChain {
+    pub latest_block_num: u64,
+    pub finalized_block_num: u64,
+    pub deals: HashMap<u64, DealInfo>,
+    pub block_hashes: HashMap<u64, H256>,
+    /// (deal id, window) => block the proof landed in
+    pub proof_blocks: HashMap<(u64, u64), BlockNum>,
+    /// (deal id, block) => proof bytes logged in that block
+    pub proof_logs: HashMap<(u64, u64), Vec<u8>>,
+    /// deal id => block the deal was created in; deals without one have always existed
+    pub deal_created: HashMap<u64, BlockNum>,
+    /// deal id => block the cancellation took effect
+    pub cancellations: HashMap<u64, BlockNum>,
+    /// `DealChain` methods that fail as if the RPC provider were down
+    pub failing: HashSet<&'static str>,
+    /// blocks the node has no hash for
+    pub missing_blocks: HashSet<u64>,
+}
+
+impl MockChain {
+    pub fn new(latest_block_num: u64) -> Self {
+        Self {
+            latest_block_num,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_deal(&mut self, deal_id: DealID, deal: DealInfo) {
+        self.deals.insert(deal_id.0, deal);
+    }
+
+    /// Records `proof` as landing in `block_num` for `window_num`, like `saveProof` would.
+    pub fn add_proof(
+        &mut self,
+        deal_id: DealID,
+        window_num: u64,
+        block_num: BlockNum,
+        proof: Vec<u8>,
+    ) {
+        self.proof_blocks.insert((deal_id.0, window_num), block_num);
+        self.proof_logs.insert((deal_id.0, block_num.0), proof);
+    }
+
+    pub fn cancel_deal(&mut self, deal_id: DealID, block_num: BlockNum) {
+        self.cancellations.insert(deal_id.0, block_num);
+    }
+
+    /// Makes every call to `method` fail from now on.
+    pub fn fail(&mut self, method: &'static str) {
+        self.failing.insert(method);
+    }
+
+    fn check(&self, method: &'static str) -> Result<()> {
+        if self.failing.contains(method) {
+            return Err(anyhow!("{method} failed"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DealChain for MockChain {
+    /// Deals created after `block_num` aren't visible yet.
+    async fn get_offer(&self, deal_id: DealID, block_num: BlockNum) -> Result<Option<DealInfo>> {
+        self.check("get_offer")?;
+        match self.deal_created.get(&deal_id.0) {
+            Some(created) if created.0 > block_num.0 => Ok(None),
+            _ => Ok(self.deals.get(&deal_id.0).cloned()),
+        }
+    }
+
+    async fn get_latest_block_num(&self) -> Result<BlockNum> {
+        self.check("get_latest_block_num")?;
+        Ok(BlockNum(self.latest_block_num))
+    }
+
+    async fn get_finalized_block_num(&self) -> Result<BlockNum> {
+        self.check("get_finalized_block_num")?;
+        Ok(BlockNum(self.finalized_block_num))
+    }
+
+    /// Blocks without an explicit hash get one derived from their number.
+    async fn get_block_hash_from_num(&self, block_num: BlockNum) -> Result<H256> {
+        self.check("get_block_hash_from_num")?;
+        if block_num.0 > self.latest_block_num {
+            return Err(anyhow!("Block {} is in the future", block_num.0));
+        }
+        if self.missing_blocks.contains(&block_num.0) {
+            return Err(anyhow!("Block {} not found", block_num.0));
+        }
+        Ok(self
+            .block_hashes
+            .get(&block_num.0)
+            .copied()
+            .unwrap_or_else(|| H256::from_low_u64_be(block_num.0)))
+    }
+
+    /// Proofs that landed after `block_num` aren't visible yet.
+    async fn get_proof_block_num_from_window(
+        &self,
+        deal_id: DealID,
+        window_num: u64,
+        block_num: BlockNum,
+    ) -> Result<Option<BlockNum>> {
+        self.check("get_proof_block_num_from_window")?;
+        Ok(self
+            .proof_blocks
+            .get(&(deal_id.0, window_num))
+            .copied()
+            .filter(|proof_block| proof_block.0 <= block_num.0))
+    }
+
+    async fn get_proof_from_logs(
+        &self,
+        block_num: BlockNum,
+        deal_id: DealID,
+    ) -> Result<Option<Vec<u8>>> {
+        self.check("get_proof_from_logs")?;
+        Ok(self.proof_logs.get(&(deal_id.0, block_num.0)).cloned())
+    }
+
+    async fn get_proofs_in_range(
+        &self,
+        deal_id: DealID,
+        from_block: BlockNum,
+        to_block: BlockNum,
+    ) -> Result<HashMap<u64, Vec<u8>>> {
+        self.check("get_proofs_in_range")?;
+        Ok(self
+            .proof_logs
+            .iter()
+            .filter(|((deal, block), _)| {
+                *deal == deal_id.0 && (from_block.0..=to_block.0).contains(block)
+            })
+            .map(|((_, block), proof)| (*block, proof.clone()))
+            .collect())
+    }
+
+    async fn get_cancellation_block(
+        &self,
+        deal_id: DealID,
+        _deal_start_block: BlockNum,
+        latest_block_num: BlockNum,
+    ) -> Result<Option<BlockNum>> {
+        self.check("get_cancellation_block")?;
+        Ok(self
+            .cancellations
+            .get(&deal_id.0)
+            .copied()
+            .filter(|block_num| block_num.0 <= latest_block_num.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// The checksum is stored as `to_hex` output, which uploaders may prefix with `0x`.
+    fn blake3_checksum_round_trips_through_hex() {
+        let hash = blake3::hash(b"banyan");
+        let hex = hash.to_hex();
+        assert_eq!(parse_blake3_checksum(hex.as_str()).unwrap(), hash);
+        assert_eq!(parse_blake3_checksum(&format!("0x{hex}")).unwrap(), hash);
+        assert_eq!(
+            parse_blake3_checksum(&hex.as_str().to_uppercase()).unwrap(),
+            hash
+        );
+    }
+
+    #[test]
+    /// Parameters too large for a u64 make the deal invalid instead of panicking.
+    fn oversized_deal_parameters_are_invalid() {
+        let deal = Deal {
+            deal_start_block: U256::from(100),
+            deal_length_in_blocks: U256::from(6),
+            proof_frequency_in_blocks: U256::from(3),
+            file_size: U256::from(1024),
+            blake3_checksum: blake3::hash(b"banyan").to_hex().to_string(),
+            ..Default::default()
+        };
+        assert!(deal_info_from_contract(&deal).unwrap().is_some());
+        assert_eq!(
+            deal_info_from_contract(&Deal::default()).unwrap(),
+            None,
+            "unknown deals read back as zeroes"
+        );
+
+        let too_long = Deal {
+            deal_length_in_blocks: U256::from(u64::MAX) + 1,
+            ..deal.clone()
+        };
+        assert!(deal_info_from_contract(&too_long).is_err());
+        let too_big = Deal {
+            file_size: U256::MAX,
+            ..deal
+        };
+        assert!(deal_info_from_contract(&too_big).is_err());
+    }
+
+    #[test]
+    /// A deal that would end past the last block has no end block, and is never over.
+    fn overflowing_deal_end_is_none() {
+        let deal = DealInfo {
+            deal_start_block: BlockNum(100),
+            deal_length_in_blocks: BlockNum(u64::MAX),
+            proof_frequency_in_blocks: BlockNum(3),
+            file_size: 1024,
+            blake3_checksum: blake3::hash(b"banyan"),
+        };
+        assert_eq!(deal.deal_end_block(), None);
+        assert!(!deal.deal_over(BlockNum(u64::MAX)));
+    }
+
+    #[test]
+    fn malformed_blake3_checksums_are_rejected() {
+        let hex = blake3::hash(b"banyan").to_hex();
+        for checksum in [
+            String::new(),
+            "0x".to_string(),
+            hex[..62].to_string(),
+            format!("{hex}00"),
+            format!("{}zz", &hex[..62]),
+            format!("{}é", &hex[..62]),
+        ] {
+            assert!(
+                parse_blake3_checksum(&checksum).is_err(),
+                "{checksum:?} was accepted"
+            );
+        }
+    }
+}
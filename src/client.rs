@@ -0,0 +1,51 @@
+use crate::{validate, ChainlinkEAError, ChainlinkEAErrorDetail};
+use banyan_shared::types::DealID;
+use rand::Rng;
+use thiserror::Error;
+use tracing::debug;
+
+/// Why `rust_chainlink_ea_api_call` didn't get a response it could use.
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Couldn't reach the adapter: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The adapter answered with a Chainlink error envelope.
+    #[error("Adapter answered {status_code}: {}: {}", .error.name, .error.message)]
+    Adapter {
+        status_code: u16,
+        error: ChainlinkEAErrorDetail,
+    },
+    #[error("Couldn't read the adapter's response: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+/// Helper function for testing inputs to Chainlink EA without having to run a node.
+pub async fn rust_chainlink_ea_api_call(
+    deal_id: DealID,
+    api_url: String,
+) -> Result<validate::ChainlinkResponse, ClientError> {
+    // Job id when chainlink calls is not random.
+    let mut rng = rand::thread_rng();
+    let random_job_id: u16 = rng.gen();
+    let map = serde_json::json!({
+        "id": random_job_id.to_string(),
+        "data":
+        {
+             "deal_id": deal_id.0.to_string()
+        }
+    });
+    let client = reqwest::Client::new();
+    let response = client.post(api_url).json(&map).send().await?;
+    let status = response.status();
+    let body = response.bytes().await?;
+    if !status.is_success() {
+        let envelope: ChainlinkEAError = serde_json::from_slice(&body)?;
+        return Err(ClientError::Adapter {
+            status_code: envelope.status_code,
+            error: envelope.error,
+        });
+    }
+    let res: validate::ChainlinkResponse = serde_json::from_slice(&body)?;
+    debug!("Adapter answered {res:?}");
+    Ok(res)
+}
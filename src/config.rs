@@ -0,0 +1,470 @@
+use crate::blocks::BlockHashFetcher;
+use crate::chain::EthChain;
+use crate::contract::ProofsContract;
+use crate::validate::{Finality, ValidationSettings};
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand};
+use ethers::types::{Address, Bytes, H256};
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Everything the adapter server can be configured with. Read from a TOML file with these keys,
+/// then overridden by environment variables and command-line flags, in that order.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address and port the adapter listens on.
+    pub address: IpAddr,
+    pub port: u16,
+    pub rpc_url: String,
+    /// Endpoint serving archive state, for reading historical contract state. `rpc_url` if unset.
+    pub archive_rpc_url: Option<String>,
+    /// If set, startup fails unless the RPC endpoint is on this chain.
+    pub chain_id: Option<u64>,
+    pub contract_address: Option<Address>,
+    /// Answer with `{"pending": true}` and PATCH the result to the `responseURL` later.
+    pub should_be_async: bool,
+    pub confirmation_depth: u64,
+    /// Pin verdicts to the node's `finalized` block instead of `confirmation_depth`.
+    pub use_finalized_tag: bool,
+    pub window_concurrency: usize,
+    pub log_block_range: u64,
+    pub block_hash_batch_size: usize,
+    pub block_hash_cache_size: usize,
+    /// `/ready` fails once the latest block is older than this, as the node is stuck or syncing.
+    pub max_block_age_secs: u64,
+    /// Bearer tokens accepted on requests to the adapter: the bridge's "Outgoing Token", which
+    /// the node sends with every bridge call. Any one of them will do, so a new token can be
+    /// added before the old one is removed. Empty accepts every request.
+    pub accepted_bridge_tokens: Vec<String>,
+    /// The bridge's incoming token, which the Chainlink node checks on async results.
+    pub bridge_incoming_token: Option<String>,
+    pub callback_max_attempts: u32,
+    pub callback_initial_backoff_ms: u64,
+    /// The backoff between callback attempts doubles up to this.
+    pub callback_max_backoff_ms: u64,
+    pub job_db_path: PathBuf,
+    /// Jobs are forgotten this long after they were last updated.
+    pub job_retention_secs: u64,
+    pub result_cache_size: usize,
+    /// Keep finalized results on disk here, if set.
+    pub result_cache_path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8000,
+            rpc_url: String::new(),
+            archive_rpc_url: None,
+            chain_id: None,
+            contract_address: None,
+            should_be_async: false,
+            confirmation_depth: 12,
+            use_finalized_tag: false,
+            window_concurrency: 8,
+            log_block_range: 2000,
+            block_hash_batch_size: 100,
+            block_hash_cache_size: 65536,
+            max_block_age_secs: 120,
+            accepted_bridge_tokens: Vec::new(),
+            bridge_incoming_token: None,
+            callback_max_attempts: 5,
+            callback_initial_backoff_ms: 500,
+            callback_max_backoff_ms: 30000,
+            job_db_path: PathBuf::from("jobs.sled"),
+            job_retention_secs: 86400,
+            result_cache_size: 1024,
+            result_cache_path: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Couldn't read config file {:?}: {e}", path))?;
+        toml::from_str(&contents).map_err(|e| anyhow!("Invalid config file {:?}: {e}", path))
+    }
+
+    /// The config file named on the command line (or defaults), with the command line's
+    /// overrides applied, validated.
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let config = Self::resolve(cli)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Like `load`, but without validating, for commands that only need part of the config.
+    pub fn resolve(cli: &Cli) -> Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        cli.overrides.clone().apply(&mut config);
+        Ok(config)
+    }
+
+    /// Checks everything that can be checked without touching the network.
+    pub fn validate(&self) -> Result<()> {
+        if self.rpc_url.is_empty() {
+            return Err(anyhow!("rpc_url must be set (--rpc-url or ETH_RPC_URL)"));
+        }
+        for url in std::iter::once(&self.rpc_url).chain(&self.archive_rpc_url) {
+            reqwest::Url::parse(url).map_err(|e| anyhow!("Invalid RPC url {url:?}: {e}"))?;
+        }
+        if self.contract_address.is_none() {
+            return Err(anyhow!(
+                "contract_address must be set (--contract-address or PROOFS_CONTRACT_ADDRESS)"
+            ));
+        }
+        if self
+            .accepted_bridge_tokens
+            .iter()
+            .any(|token| token.trim().is_empty())
+        {
+            return Err(anyhow!(
+                "accepted_bridge_tokens can't contain an empty token"
+            ));
+        }
+        let positive = [
+            ("window_concurrency", self.window_concurrency as u64),
+            ("log_block_range", self.log_block_range),
+            ("block_hash_batch_size", self.block_hash_batch_size as u64),
+            ("block_hash_cache_size", self.block_hash_cache_size as u64),
+            ("callback_max_attempts", self.callback_max_attempts as u64),
+            ("result_cache_size", self.result_cache_size as u64),
+            ("max_block_age_secs", self.max_block_age_secs),
+            ("job_retention_secs", self.job_retention_secs),
+        ];
+        for (name, value) in positive {
+            if value == 0 {
+                return Err(anyhow!("{name} must be greater than 0"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles on the node and the Proofs contract, checking the chain id if one is configured.
+    pub async fn connect(&self) -> Result<EthChain> {
+        // a read-only handle on the Proofs contract, for contract state as of any block
+        let contract = ProofsContract::new(
+            self.archive_rpc_url(),
+            self.contract_address
+                .ok_or_else(|| anyhow!("contract_address must be set"))?,
+            self.log_block_range,
+        )?;
+        // block hashes are fetched in JSON-RPC batches and cached once final
+        let block_hashes = BlockHashFetcher::new(
+            self.rpc_url.clone(),
+            self.block_hash_batch_size,
+            self.block_hash_cache_size,
+            self.confirmation_depth,
+        )?;
+        if let Some(expected) = self.chain_id {
+            let actual = block_hashes.get_chain_id().await?;
+            if actual != expected {
+                return Err(anyhow!(
+                    "{} is on chain {actual}, but chain_id is {expected}",
+                    self.rpc_url
+                ));
+            }
+        }
+        Ok(EthChain {
+            contract,
+            block_hashes,
+        })
+    }
+
+    pub fn archive_rpc_url(&self) -> &str {
+        self.archive_rpc_url.as_deref().unwrap_or(&self.rpc_url)
+    }
+
+    pub fn callback_initial_backoff(&self) -> Duration {
+        Duration::from_millis(self.callback_initial_backoff_ms)
+    }
+
+    pub fn callback_max_backoff(&self) -> Duration {
+        Duration::from_millis(self.callback_max_backoff_ms)
+    }
+
+    pub fn job_retention(&self) -> Duration {
+        Duration::from_secs(self.job_retention_secs)
+    }
+
+    pub fn max_block_age(&self) -> Duration {
+        Duration::from_secs(self.max_block_age_secs)
+    }
+
+    pub fn validation_settings(&self) -> ValidationSettings {
+        ValidationSettings {
+            window_concurrency: self.window_concurrency,
+            finality: if self.use_finalized_tag {
+                Finality::FinalizedTag
+            } else {
+                Finality::ConfirmationDepth(self.confirmation_depth)
+            },
+        }
+    }
+}
+
+/// Chainlink external adapter that validates Banyan storage deals.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML config file. Environment variables and flags override its values.
+    #[arg(long, short, global = true, env = "CONFIG_PATH")]
+    pub config: Option<PathBuf>,
+    #[command(flatten)]
+    pub overrides: Overrides,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Re-run the validation recorded in an evidence bundle, without any chain access.
+    VerifyBundle { path: PathBuf },
+    /// Validate a deal in-process, without starting the server, and print the response with a
+    /// per-window table.
+    Validate {
+        #[arg(long)]
+        deal_id: u64,
+        /// Print the response as JSON instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a deal's parameters, status and proof window schedule as of the latest block.
+    Inspect {
+        #[arg(long)]
+        deal_id: u64,
+        /// Print the inspection as JSON instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check a proof against the original file, without the chain.
+    CheckProof(CheckProofArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CheckProofArgs {
+    /// The file the deal stores.
+    #[arg(long)]
+    pub file: PathBuf,
+    /// Hash of the window's target block, which chooses the chunk to prove.
+    #[arg(
+        long,
+        required_unless_present = "block_number",
+        conflicts_with = "block_number"
+    )]
+    pub block_hash: Option<H256>,
+    /// Number of the target block, whose hash is fetched from `rpc_url`.
+    #[arg(long)]
+    pub block_number: Option<u64>,
+    /// The proof, in hex.
+    #[arg(
+        long,
+        required_unless_present = "proof_file",
+        conflicts_with = "proof_file"
+    )]
+    pub proof: Option<Bytes>,
+    /// A file holding the raw proof bytes.
+    #[arg(long)]
+    pub proof_file: Option<PathBuf>,
+    /// Offset of the chunk the proof was made for, to compare with the expected one.
+    #[arg(long, requires = "size")]
+    pub offset: Option<u64>,
+    /// Size of the chunk the proof was made for.
+    #[arg(long, requires = "offset")]
+    pub size: Option<u64>,
+    /// Print the result as JSON instead.
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Flags (or environment variables) that override the config file. See `Config` for what they mean.
+/// They can be given before or after a subcommand.
+#[derive(Args, Debug, Clone, Default)]
+pub struct Overrides {
+    #[arg(long, global = true, env = "BIND_ADDRESS")]
+    pub address: Option<IpAddr>,
+    #[arg(long, global = true, env = "PORT")]
+    pub port: Option<u16>,
+    #[arg(long, global = true, env = "ETH_RPC_URL")]
+    pub rpc_url: Option<String>,
+    #[arg(long, global = true, env = "ARCHIVE_RPC_URL")]
+    pub archive_rpc_url: Option<String>,
+    #[arg(long, global = true, env = "CHAIN_ID")]
+    pub chain_id: Option<u64>,
+    #[arg(
+        long,
+        alias = "contract",
+        global = true,
+        env = "PROOFS_CONTRACT_ADDRESS"
+    )]
+    pub contract_address: Option<Address>,
+    #[arg(long, global = true, env = "SHOULD_BE_ASYNC")]
+    pub should_be_async: Option<bool>,
+    #[arg(long, global = true, env = "CONFIRMATION_DEPTH")]
+    pub confirmation_depth: Option<u64>,
+    #[arg(long, global = true, env = "USE_FINALIZED_TAG")]
+    pub use_finalized_tag: Option<bool>,
+    #[arg(long, global = true, env = "WINDOW_CONCURRENCY")]
+    pub window_concurrency: Option<usize>,
+    #[arg(long, global = true, env = "LOG_BLOCK_RANGE")]
+    pub log_block_range: Option<u64>,
+    #[arg(long, global = true, env = "BLOCK_HASH_BATCH_SIZE")]
+    pub block_hash_batch_size: Option<usize>,
+    #[arg(long, global = true, env = "BLOCK_HASH_CACHE_SIZE")]
+    pub block_hash_cache_size: Option<usize>,
+    #[arg(long, global = true, env = "MAX_BLOCK_AGE_SECS")]
+    pub max_block_age_secs: Option<u64>,
+    /// Repeat the flag, or separate the tokens with commas in the variable, for several tokens.
+    #[arg(
+        long = "accepted-bridge-token",
+        global = true,
+        env = "ACCEPTED_BRIDGE_TOKENS",
+        value_delimiter = ',',
+        hide_env_values = true
+    )]
+    pub accepted_bridge_tokens: Option<Vec<String>>,
+    #[arg(
+        long,
+        global = true,
+        env = "BRIDGE_INCOMING_TOKEN",
+        hide_env_values = true
+    )]
+    pub bridge_incoming_token: Option<String>,
+    #[arg(long, global = true, env = "CALLBACK_MAX_ATTEMPTS")]
+    pub callback_max_attempts: Option<u32>,
+    #[arg(long, global = true, env = "CALLBACK_INITIAL_BACKOFF_MS")]
+    pub callback_initial_backoff_ms: Option<u64>,
+    #[arg(long, global = true, env = "CALLBACK_MAX_BACKOFF_MS")]
+    pub callback_max_backoff_ms: Option<u64>,
+    #[arg(long, global = true, env = "JOB_DB_PATH")]
+    pub job_db_path: Option<PathBuf>,
+    #[arg(long, global = true, env = "JOB_RETENTION_SECS")]
+    pub job_retention_secs: Option<u64>,
+    #[arg(long, global = true, env = "RESULT_CACHE_SIZE")]
+    pub result_cache_size: Option<usize>,
+    #[arg(long, global = true, env = "RESULT_CACHE_PATH")]
+    pub result_cache_path: Option<PathBuf>,
+}
+
+impl Overrides {
+    pub fn apply(self, config: &mut Config) {
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(if let Some(value) = self.$field {
+                    config.$field = value;
+                })*
+            };
+        }
+        macro_rules! set_optional {
+            ($($field:ident),*) => {
+                $(if let Some(value) = self.$field {
+                    config.$field = Some(value);
+                })*
+            };
+        }
+        set!(
+            address,
+            port,
+            rpc_url,
+            should_be_async,
+            confirmation_depth,
+            use_finalized_tag,
+            window_concurrency,
+            log_block_range,
+            block_hash_batch_size,
+            block_hash_cache_size,
+            max_block_age_secs,
+            accepted_bridge_tokens,
+            callback_max_attempts,
+            callback_initial_backoff_ms,
+            callback_max_backoff_ms,
+            job_db_path,
+            job_retention_secs,
+            result_cache_size
+        );
+        set_optional!(
+            archive_rpc_url,
+            chain_id,
+            contract_address,
+            bridge_incoming_token,
+            result_cache_path
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Flags win over the file, and an incomplete config is rejected with the missing setting named.
+    fn overrides_apply_over_file() {
+        let mut config: Config = toml::from_str(
+            r#"
+            rpc_url = "https://goerli.infura.io/v3/key"
+            port = 9000
+            window_concurrency = 4
+            "#,
+        )
+        .expect("valid toml");
+        let error = config.validate().expect_err("no contract address");
+        assert!(error.to_string().contains("contract_address"));
+
+        Overrides {
+            port: Some(9001),
+            contract_address: Some(Address::repeat_byte(1)),
+            ..Default::default()
+        }
+        .apply(&mut config);
+        config.validate().expect("complete config");
+        assert_eq!(config.port, 9001);
+        assert_eq!(config.window_concurrency, 4);
+        assert_eq!(config.confirmation_depth, 12);
+    }
+
+    #[test]
+    /// Connection flags work after the subcommand too, as in `validate --deal-id 42 --rpc-url ...`.
+    fn validate_takes_overrides_after_subcommand() {
+        let cli = Cli::try_parse_from([
+            "chainlink-proof-validator",
+            "validate",
+            "--deal-id",
+            "42",
+            "--json",
+            "--rpc-url",
+            "http://localhost:8545",
+            "--contract",
+            "0x0101010101010101010101010101010101010101",
+        ])
+        .expect("valid command line");
+        assert!(matches!(
+            cli.command,
+            Some(Command::Validate {
+                deal_id: 42,
+                json: true
+            })
+        ));
+        assert_eq!(
+            cli.overrides.rpc_url.as_deref(),
+            Some("http://localhost:8545")
+        );
+        assert_eq!(
+            cli.overrides.contract_address,
+            Some(Address::repeat_byte(1))
+        );
+    }
+
+    #[test]
+    /// A misspelt key is an error rather than silently falling back to the default.
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("rpc_ur = \"http://localhost:8545\"").is_err());
+    }
+}
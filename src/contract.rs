@@ -0,0 +1,187 @@
+use anyhow::{anyhow, Result};
+use banyan_shared::types::{BlockNum, DealID};
+use ethers::{
+    contract::abigen,
+    providers::{Http, Middleware, Provider},
+    types::{Address, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// The parts of the Proofs contract ABI the validator reads.
+abigen!(
+    ProofsBindings,
+    r#"[
+        struct OfferCounterpart { uint256 amount; address partyAddress; bool cancel; }
+        struct Deal { uint256 dealStartBlock; uint256 dealLengthInBlocks; uint256 proofFrequencyInBlocks; uint256 price; uint256 collateral; address erc20TokenDenomination; string ipfsFileCID; uint256 fileSize; string blake3Checksum; OfferCounterpart creatorCounterpart; OfferCounterpart providerCounterpart; uint8 offerStatus; }
+        function getDeal(uint256 offerID) external view returns (Deal)
+        function getProofBlock(uint256 offerID, uint256 windowNum) external view returns (uint256)
+        event ProofAdded(uint256 indexed offerId, uint256 indexed blockNumber, bytes proof)
+    ]"#
+);
+
+/// Mirrors `Proofs.OfferStatus`, in the same order as the Solidity enum.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfferStatus {
+    Non,
+    OfferCreated,
+    OfferAccepted,
+    OfferActive,
+    OfferCompleted,
+    OfferFinalized,
+    OfferTimedout,
+    OfferCancelled,
+}
+
+impl TryFrom<u8> for OfferStatus {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => OfferStatus::Non,
+            1 => OfferStatus::OfferCreated,
+            2 => OfferStatus::OfferAccepted,
+            3 => OfferStatus::OfferActive,
+            4 => OfferStatus::OfferCompleted,
+            5 => OfferStatus::OfferFinalized,
+            6 => OfferStatus::OfferTimedout,
+            7 => OfferStatus::OfferCancelled,
+            _ => return Err(anyhow!("Unknown offer status {value}")),
+        })
+    }
+}
+
+/// Read-only handle on the Proofs contract, for reading its state as of any block.
+pub struct ProofsContract {
+    contract: ProofsBindings<Provider<Http>>,
+    /// Most blocks asked for in one `eth_getLogs`, since providers cap the range.
+    log_block_range: u64,
+}
+
+impl ProofsContract {
+    pub fn new(rpc_url: &str, contract_address: Address, log_block_range: u64) -> Result<Self> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| anyhow!("Invalid RPC url {rpc_url}: {e}"))?;
+        Ok(Self {
+            contract: ProofsBindings::new(contract_address, Arc::new(provider)),
+            log_block_range: log_block_range.max(1),
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    /// Whether there's code at the contract's address, i.e. the contract is deployed on the
+    /// chain the RPC endpoint is on.
+    pub async fn has_code(&self) -> Result<bool> {
+        let code = self
+            .contract
+            .client()
+            .get_code(self.contract.address(), None)
+            .await?;
+        Ok(!code.is_empty())
+    }
+
+    /// Every `ProofAdded` log for the deal between `from_block` and `to_block` inclusive, keyed
+    /// by the block it was emitted in. Queried in chunks of `log_block_range` blocks. If a block
+    /// holds more than one proof for the deal, the first one wins, like `get_proof_from_logs`.
+    pub async fn get_proofs_in_range(
+        &self,
+        deal_id: DealID,
+        from_block: BlockNum,
+        to_block: BlockNum,
+    ) -> Result<HashMap<u64, Vec<u8>>> {
+        let mut proofs = HashMap::new();
+        let mut chunk_start = from_block.0;
+        while chunk_start <= to_block.0 {
+            let chunk_end = to_block
+                .0
+                .min(chunk_start.saturating_add(self.log_block_range - 1));
+            let logs = self
+                .contract
+                .proof_added_filter()
+                .topic1(H256::from_low_u64_be(deal_id.0))
+                .from_block(chunk_start)
+                .to_block(chunk_end)
+                .query()
+                .await
+                .map_err(|e| {
+                    anyhow!("Error in ProofAdded logs for blocks {chunk_start}..={chunk_end}: {e}")
+                })?;
+            for log in logs {
+                proofs
+                    .entry(log.block_number.as_u64())
+                    .or_insert_with(|| log.proof.to_vec());
+            }
+            chunk_start = chunk_end + 1;
+        }
+        Ok(proofs)
+    }
+
+    /// The deal as it was at `block_num`. Unknown deal ids read back as all zeroes.
+    pub async fn get_deal(&self, deal_id: DealID, block_num: BlockNum) -> Result<Deal> {
+        self.contract
+            .get_deal(U256::from(deal_id.0))
+            .block(block_num.0)
+            .call()
+            .await
+            .map_err(|e| anyhow!("Error in getDeal at block {}: {e}", block_num.0))
+    }
+
+    /// Block the proof for `window_num` landed in, as recorded at `block_num`. None if no proof
+    /// had been submitted for the window by then.
+    pub async fn get_proof_block(
+        &self,
+        deal_id: DealID,
+        window_num: u64,
+        block_num: BlockNum,
+    ) -> Result<Option<BlockNum>> {
+        let proof_block = self
+            .contract
+            .get_proof_block(U256::from(deal_id.0), U256::from(window_num))
+            .block(block_num.0)
+            .call()
+            .await
+            .map_err(|e| anyhow!("Error in getProofBlock at block {}: {e}", block_num.0))?;
+        Ok((!proof_block.is_zero()).then(|| BlockNum(proof_block.as_u64())))
+    }
+
+    async fn is_cancelled_at(&self, deal_id: DealID, block_num: BlockNum) -> Result<bool> {
+        let deal = self.get_deal(deal_id, block_num).await?;
+        let status = OfferStatus::try_from(deal.offer_status)?;
+        // A deal is cancelled either by the contract or once both parties have agreed to cancel.
+        Ok(status == OfferStatus::OfferCancelled
+            || (deal.creator_counterpart.cancel && deal.provider_counterpart.cancel))
+    }
+
+    /// Returns the first block at which the deal was cancelled, or None if it is not cancelled
+    /// as of `latest_block_num`. The contract does not record when a cancellation happened, so
+    /// this bisects historical state between the deal start and `latest_block_num`, which needs
+    /// an RPC endpoint that serves archive state.
+    pub async fn get_cancellation_block(
+        &self,
+        deal_id: DealID,
+        deal_start_block: BlockNum,
+        latest_block_num: BlockNum,
+    ) -> Result<Option<BlockNum>> {
+        if !self.is_cancelled_at(deal_id, latest_block_num).await? {
+            return Ok(None);
+        }
+        if self.is_cancelled_at(deal_id, deal_start_block).await? {
+            return Ok(Some(deal_start_block));
+        }
+        // invariant: not cancelled at `low`, cancelled at `high`
+        let (mut low, mut high) = (deal_start_block.0, latest_block_num.0);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if self.is_cancelled_at(deal_id, BlockNum(mid)).await? {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+        Ok(Some(BlockNum(high)))
+    }
+}
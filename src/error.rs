@@ -0,0 +1,94 @@
+use banyan_shared::types::{BlockNum, DealID};
+use thiserror::Error;
+
+/// `ResponseData::status` written on-chain when a deal was validated.
+pub const STATUS_OK: u16 = 1;
+
+/// Everything that can stop a deal from being validated. The `status` and `http_status` of each
+/// variant are part of the adapter's contract with the chain and with alerting, so don't renumber them.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    #[error("Invalid deal_id {0:?}")]
+    InvalidDealId(String),
+    #[error("Deal {} not found", .0.0)]
+    DealNotFound(DealID),
+    #[error("Deal {} is ongoing until block {}", .deal_id.0, .deal_end_block.0)]
+    DealOngoing {
+        deal_id: DealID,
+        deal_end_block: BlockNum,
+    },
+    #[error(
+        "Deal {} can't be validated until block {} is final, finalized up to block {}",
+        .deal_id.0, .final_block.0, .pinned_block.0
+    )]
+    NotFinalized {
+        deal_id: DealID,
+        final_block: BlockNum,
+        pinned_block: BlockNum,
+    },
+    #[error("Invalid parameters for deal {}: {reason}", .deal_id.0)]
+    InvalidDealParameters { deal_id: DealID, reason: String },
+    #[error("RPC unavailable: {0}")]
+    RpcUnavailable(String),
+    #[error("Could not get block hash for block {}: {reason}", .block_num.0)]
+    BlockHashUnavailable { block_num: BlockNum, reason: String },
+    #[error("Couldn't get proof log from block {}: {reason}", .block_num.0)]
+    LogDecodeFailure { block_num: BlockNum, reason: String },
+}
+
+impl ValidationError {
+    /// Stable `ResponseData::status` for this failure. `STATUS_OK` is never used here.
+    pub fn status(&self) -> u16 {
+        match self {
+            ValidationError::InvalidDealId(_) => 2,
+            ValidationError::DealNotFound(_) => 3,
+            ValidationError::DealOngoing { .. } => 4,
+            ValidationError::InvalidDealParameters { .. } => 5,
+            ValidationError::RpcUnavailable(_) => 6,
+            ValidationError::BlockHashUnavailable { .. } => 7,
+            ValidationError::LogDecodeFailure { .. } => 8,
+            // 9 was a proof block read failure, now `RpcUnavailable`; don't reuse it
+            ValidationError::NotFinalized { .. } => 10,
+        }
+    }
+
+    /// HTTP status the adapter answers with. Failures that are a final answer about the deal are
+    /// 200 so that their `status` reaches the contract; the rest fail the bridge task.
+    pub fn http_status(&self) -> u16 {
+        match self {
+            ValidationError::DealNotFound(_)
+            | ValidationError::DealOngoing { .. }
+            | ValidationError::NotFinalized { .. }
+            | ValidationError::InvalidDealParameters { .. } => 200,
+            ValidationError::InvalidDealId(_) => 400,
+            ValidationError::RpcUnavailable(_) => 503,
+            ValidationError::BlockHashUnavailable { .. }
+            | ValidationError::LogDecodeFailure { .. } => 502,
+        }
+    }
+
+    /// The deal a final answer is about, for writing it on-chain.
+    pub fn deal_id(&self) -> Option<DealID> {
+        match self {
+            ValidationError::DealNotFound(deal_id)
+            | ValidationError::DealOngoing { deal_id, .. }
+            | ValidationError::NotFinalized { deal_id, .. }
+            | ValidationError::InvalidDealParameters { deal_id, .. } => Some(*deal_id),
+            _ => None,
+        }
+    }
+
+    /// Name reported in the `error.name` of a Chainlink error envelope.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValidationError::InvalidDealId(_) => "InvalidDealId",
+            ValidationError::DealNotFound(_) => "DealNotFound",
+            ValidationError::DealOngoing { .. } => "DealOngoing",
+            ValidationError::NotFinalized { .. } => "NotFinalized",
+            ValidationError::InvalidDealParameters { .. } => "InvalidDealParameters",
+            ValidationError::RpcUnavailable(_) => "RpcUnavailable",
+            ValidationError::BlockHashUnavailable { .. } => "BlockHashUnavailable",
+            ValidationError::LogDecodeFailure { .. } => "LogDecodeFailure",
+        }
+    }
+}
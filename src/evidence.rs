@@ -0,0 +1,151 @@
+use crate::chain::{parse_blake3_checksum, DealInfo};
+use crate::validate::{self, ChainlinkResponse, WindowReport};
+use anyhow::{anyhow, Result};
+use banyan_shared::types::{BlockNum, DealID};
+use ethers::types::{Bytes, H256};
+use serde::{Deserialize, Serialize};
+
+/// The deal as it was read at the pinned block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DealEvidence {
+    pub deal_id: DealID,
+    pub deal_start_block: BlockNum,
+    pub deal_length_in_blocks: BlockNum,
+    pub proof_frequency_in_blocks: BlockNum,
+    pub file_size: u64,
+    /// Hex, as stored in the contract.
+    pub blake3_checksum: String,
+}
+
+impl DealEvidence {
+    pub fn new(deal_id: DealID, deal_info: &DealInfo) -> Self {
+        Self {
+            deal_id,
+            deal_start_block: deal_info.deal_start_block,
+            deal_length_in_blocks: deal_info.deal_length_in_blocks,
+            proof_frequency_in_blocks: deal_info.proof_frequency_in_blocks,
+            file_size: deal_info.file_size,
+            blake3_checksum: deal_info.blake3_checksum.to_hex().to_string(),
+        }
+    }
+
+    pub fn deal_info(&self) -> Result<DealInfo> {
+        Ok(DealInfo {
+            deal_start_block: self.deal_start_block,
+            deal_length_in_blocks: self.deal_length_in_blocks,
+            proof_frequency_in_blocks: self.proof_frequency_in_blocks,
+            file_size: self.file_size,
+            blake3_checksum: parse_blake3_checksum(&self.blake3_checksum)?,
+        })
+    }
+}
+
+/// What was read from the chain for one window.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WindowEvidence {
+    pub window_num: usize,
+    pub target_block: BlockNum,
+    pub target_block_hash: H256,
+    pub proof_block: Option<BlockNum>,
+    /// The proof exactly as logged in `proof_block`, if there was a log.
+    pub proof: Option<Bytes>,
+    /// The chunk the proof had to cover. Recomputed from `target_block_hash` when verifying.
+    pub chunk_offset: u64,
+    pub chunk_size: u64,
+}
+
+impl WindowEvidence {
+    pub fn new(report: &WindowReport, proof: Option<Vec<u8>>) -> Self {
+        Self {
+            window_num: report.window_num,
+            target_block: report.target_block,
+            target_block_hash: report.target_block_hash,
+            proof_block: report.proof_block,
+            proof: proof.map(Bytes::from),
+            chunk_offset: report.chunk_offset,
+            chunk_size: report.chunk_size,
+        }
+    }
+}
+
+/// Everything a verdict was computed from, so it can be re-checked later without an RPC provider.
+/// The block hashes are the only chain data that has to be trusted, and they can be checked
+/// against any block explorer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvidenceBundle {
+    pub deal: DealEvidence,
+    /// When the deal was cancelled, if it was cancelled before its end.
+    pub cancellation_block: Option<BlockNum>,
+    pub pinned_block: BlockNum,
+    pub pinned_block_hash: H256,
+    pub windows: Vec<WindowEvidence>,
+    /// The answer the validator gave, with the per-window breakdown.
+    pub response: ChainlinkResponse,
+}
+
+impl EvidenceBundle {
+    /// Recomputes the answer purely from the bundle, with the same chunk choice and merkle proof
+    /// checks as a live validation.
+    pub fn verify(&self) -> Result<ChainlinkResponse> {
+        let deal_id = self.deal.deal_id;
+        let deal_info = self.deal.deal_info()?;
+        let deal_end_block = validate::checked_end_block(deal_id, &deal_info)?;
+        // a live validation only ever records a cancellation during the deal
+        if let Some(cancellation_block) = self.cancellation_block {
+            if cancellation_block.0 < deal_info.deal_start_block.0
+                || cancellation_block.0 >= deal_end_block.0
+            {
+                return Err(anyhow!(
+                    "Bundle cancellation block {} is outside the deal, blocks {}..{}",
+                    cancellation_block.0,
+                    deal_info.deal_start_block.0,
+                    deal_end_block.0
+                ));
+            }
+        }
+        let num_windows = validate::count_windows(deal_id, &deal_info, self.cancellation_block)?;
+        if num_windows != self.windows.len() {
+            return Err(anyhow!(
+                "Deal has {num_windows} windows but the bundle has {}",
+                self.windows.len()
+            ));
+        }
+
+        let mut window_reports = Vec::with_capacity(num_windows);
+        for (window_num, window) in self.windows.iter().enumerate() {
+            let mut report =
+                validate::new_window_report(&deal_info, window_num, window.target_block_hash);
+            if window.window_num != window_num || window.target_block != report.target_block {
+                return Err(anyhow!(
+                    "Bundle window {} targets block {}, expected window {window_num} targeting block {}",
+                    window.window_num,
+                    window.target_block.0,
+                    report.target_block.0
+                ));
+            }
+            if (window.chunk_offset, window.chunk_size) != (report.chunk_offset, report.chunk_size)
+            {
+                return Err(anyhow!(
+                    "Bundle window {window_num} names chunk {}+{}, but its target block hash chooses {}+{}",
+                    window.chunk_offset,
+                    window.chunk_size,
+                    report.chunk_offset,
+                    report.chunk_size
+                ));
+            }
+            report.proof_block = window.proof_block;
+            validate::judge_window(
+                &mut report,
+                window.proof.as_ref().map(|proof| proof.to_vec()),
+                deal_info.blake3_checksum,
+            );
+            window_reports.push(report);
+        }
+        Ok(validate::build_response(
+            deal_id,
+            self.pinned_block,
+            self.pinned_block_hash,
+            window_reports,
+        ))
+    }
+}
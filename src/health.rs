@@ -0,0 +1,244 @@
+use crate::chain::EthChain;
+use anyhow::Result;
+use async_trait::async_trait;
+use banyan_shared::types::BlockNum;
+use ethers::types::Address;
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The reads the readiness checks make. `EthChain` answers them from the RPC endpoint.
+#[async_trait]
+pub trait ReadinessProbe: Send + Sync {
+    async fn chain_id(&self) -> Result<u64>;
+
+    fn contract_address(&self) -> Address;
+
+    async fn contract_has_code(&self) -> Result<bool>;
+
+    /// Number and unix timestamp of the latest block.
+    async fn latest_block(&self) -> Result<(BlockNum, u64)>;
+}
+
+#[async_trait]
+impl ReadinessProbe for EthChain {
+    async fn chain_id(&self) -> Result<u64> {
+        self.block_hashes.get_chain_id().await
+    }
+
+    fn contract_address(&self) -> Address {
+        self.contract.address()
+    }
+
+    async fn contract_has_code(&self) -> Result<bool> {
+        self.contract.has_code().await
+    }
+
+    async fn latest_block(&self) -> Result<(BlockNum, u64)> {
+        self.block_hashes.get_latest_block().await
+    }
+}
+
+/// Outcome of one readiness check, with what was seen either way.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Check {
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(detail: String) -> Self {
+        Self { ok: true, detail }
+    }
+
+    fn fail(detail: String) -> Self {
+        Self { ok: false, detail }
+    }
+}
+
+/// Whether the adapter can currently validate deals, check by check.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    /// The RPC endpoint answers at all.
+    pub rpc: Check,
+    /// The endpoint is on the configured chain, if one is configured.
+    pub chain_id: Check,
+    /// The Proofs contract is deployed at the configured address.
+    pub contract_code: Check,
+    /// The latest block is recent, so the node isn't stuck or still syncing.
+    pub block_advancing: Check,
+}
+
+impl Readiness {
+    /// 200 when ready, 503 otherwise.
+    pub fn http_status(&self) -> u16 {
+        if self.ready {
+            200
+        } else {
+            503
+        }
+    }
+}
+
+/// Runs every readiness check against the chain at once. A block counts as advancing while the
+/// latest block is at most `max_block_age` old.
+pub async fn check_readiness<P: ReadinessProbe + ?Sized>(
+    chain: &P,
+    expected_chain_id: Option<u64>,
+    max_block_age: Duration,
+) -> Readiness {
+    let (chain_id, has_code, latest) = futures::join!(
+        chain.chain_id(),
+        chain.contract_has_code(),
+        chain.latest_block(),
+    );
+
+    let (rpc, chain_id) = match chain_id {
+        Ok(actual) => (
+            Check::pass(format!("eth_chainId answered {actual}")),
+            match expected_chain_id {
+                Some(expected) if expected != actual => {
+                    Check::fail(format!("On chain {actual}, expected {expected}"))
+                }
+                Some(_) => Check::pass(format!("On chain {actual}")),
+                None => Check::pass(format!("On chain {actual}, no chain_id configured")),
+            },
+        ),
+        Err(e) => (
+            Check::fail(format!("eth_chainId failed: {e}")),
+            Check::fail("RPC unreachable".to_string()),
+        ),
+    };
+    let address = chain.contract_address();
+    let contract_code = match has_code {
+        Ok(true) => Check::pass(format!("Code found at {address:?}")),
+        Ok(false) => Check::fail(format!("No code at {address:?}")),
+        Err(e) => Check::fail(format!("eth_getCode failed: {e}")),
+    };
+    let block_advancing = match latest {
+        Ok((block_num, timestamp)) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let age = now.saturating_sub(timestamp);
+            if age <= max_block_age.as_secs() {
+                Check::pass(format!("Latest block {} is {age}s old", block_num.0))
+            } else {
+                Check::fail(format!(
+                    "Latest block {} is {age}s old, more than {}s",
+                    block_num.0,
+                    max_block_age.as_secs()
+                ))
+            }
+        }
+        Err(e) => Check::fail(format!("Couldn't get latest block: {e}")),
+    };
+
+    Readiness {
+        ready: rpc.ok && chain_id.ok && contract_code.ok && block_advancing.ok,
+        rpc,
+        chain_id,
+        contract_code,
+        block_advancing,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    /// Answers each read with a fixed value, or an RPC error where it's None.
+    struct StubProbe {
+        chain_id: Option<u64>,
+        has_code: Option<bool>,
+        /// How old the latest block is, in seconds.
+        latest_block_age: Option<u64>,
+    }
+
+    impl StubProbe {
+        fn healthy() -> Self {
+            Self {
+                chain_id: Some(5),
+                has_code: Some(true),
+                latest_block_age: Some(10),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ReadinessProbe for StubProbe {
+        async fn chain_id(&self) -> Result<u64> {
+            self.chain_id.ok_or_else(|| anyhow!("connection refused"))
+        }
+
+        fn contract_address(&self) -> Address {
+            Address::zero()
+        }
+
+        async fn contract_has_code(&self) -> Result<bool> {
+            self.has_code.ok_or_else(|| anyhow!("connection refused"))
+        }
+
+        async fn latest_block(&self) -> Result<(BlockNum, u64)> {
+            let age = self
+                .latest_block_age
+                .ok_or_else(|| anyhow!("connection refused"))?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            Ok((BlockNum(100), now - age))
+        }
+    }
+
+    async fn failing_checks(probe: StubProbe) -> (u16, Vec<&'static str>) {
+        let readiness = check_readiness(&probe, Some(5), Duration::from_secs(60)).await;
+        let checks = [
+            ("rpc", &readiness.rpc),
+            ("chain_id", &readiness.chain_id),
+            ("contract_code", &readiness.contract_code),
+            ("block_advancing", &readiness.block_advancing),
+        ];
+        let failing = checks
+            .iter()
+            .filter(|(_, check)| !check.ok)
+            .map(|(name, _)| *name)
+            .collect();
+        (readiness.http_status(), failing)
+    }
+
+    #[tokio::test]
+    async fn healthy_chain_is_ready() {
+        assert_eq!(failing_checks(StubProbe::healthy()).await, (200, vec![]));
+    }
+
+    #[tokio::test]
+    /// Each problem makes the adapter unready and shows up in its own check.
+    async fn each_failure_fails_its_check() {
+        let wrong_chain = StubProbe {
+            chain_id: Some(1),
+            ..StubProbe::healthy()
+        };
+        assert_eq!(failing_checks(wrong_chain).await, (503, vec!["chain_id"]));
+
+        let no_code = StubProbe {
+            has_code: Some(false),
+            ..StubProbe::healthy()
+        };
+        assert_eq!(failing_checks(no_code).await, (503, vec!["contract_code"]));
+
+        let stale = StubProbe {
+            latest_block_age: Some(600),
+            ..StubProbe::healthy()
+        };
+        assert_eq!(failing_checks(stale).await, (503, vec!["block_advancing"]));
+
+        let rpc_down = StubProbe {
+            chain_id: None,
+            ..StubProbe::healthy()
+        };
+        assert_eq!(
+            failing_checks(rpc_down).await,
+            (503, vec!["rpc", "chain_id"])
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use crate::chain::DealChain;
+use crate::error::ValidationError;
+use crate::evidence::DealEvidence;
+use crate::validate::{self, ValidationSettings};
+use banyan_shared::types::{BlockNum, DealID};
+use ethers::types::H256;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+/// Where a deal is in its life as of the latest block.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DealStatus {
+    Active,
+    Over,
+    Cancelled,
+}
+
+/// One proof window, and what the chain knows about it so far.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WindowSchedule {
+    pub window_num: usize,
+    /// First block of the window, whose hash chooses the chunk to prove.
+    pub start_block: BlockNum,
+    /// Last block of the window.
+    pub end_block: BlockNum,
+    /// Hash of `start_block`, once it has been mined.
+    pub target_block_hash: Option<H256>,
+    /// Block a proof for the window landed in, per `getProofBlock`.
+    pub proof_block: Option<BlockNum>,
+}
+
+/// A deal's parameters and proof schedule as of the latest block, for answering "which block
+/// do I prove against next?" without working it out by hand.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DealInspection {
+    pub deal: DealEvidence,
+    pub status: DealStatus,
+    pub deal_end_block: BlockNum,
+    pub cancellation_block: Option<BlockNum>,
+    pub latest_block: BlockNum,
+    /// The window the latest block falls in, while the deal is active.
+    pub current_window: Option<usize>,
+    pub windows: Vec<WindowSchedule>,
+}
+
+/// Reads everything about the deal as of the latest block. Unlike a validation nothing is
+/// pinned, so a reorg can still change what's reported for the most recent blocks.
+pub async fn inspect_deal<C: DealChain + ?Sized>(
+    chain: &C,
+    settings: &ValidationSettings,
+    deal_id: DealID,
+) -> Result<DealInspection, ValidationError> {
+    let latest_block = validate::read_latest_block(chain).await?;
+    let deal_info = validate::read_offer(chain, deal_id, latest_block)
+        .await?
+        .ok_or(ValidationError::DealNotFound(deal_id))?;
+    let (deal_end_block, cancellation_block) =
+        validate::read_deal_end(chain, deal_id, &deal_info, latest_block).await?;
+
+    let status = if cancellation_block.is_some() {
+        DealStatus::Cancelled
+    } else if deal_info.deal_over(latest_block) {
+        DealStatus::Over
+    } else {
+        DealStatus::Active
+    };
+    let num_windows = validate::count_windows(deal_id, &deal_info, cancellation_block)?;
+    let frequency = deal_info.proof_frequency_in_blocks.0;
+    let current_window = match status {
+        DealStatus::Active => latest_block
+            .0
+            .checked_sub(deal_info.deal_start_block.0)
+            .and_then(|blocks| blocks.checked_div(frequency))
+            .map(|window_num| window_num as usize),
+        _ => None,
+    };
+
+    let start_blocks: Vec<BlockNum> = (0..num_windows)
+        .map(|window_num| validate::target_block(&deal_info, window_num))
+        .collect();
+    // windows that haven't started have no target block hash or proof yet
+    let started: Vec<BlockNum> = start_blocks
+        .iter()
+        .copied()
+        .filter(|start_block| start_block.0 <= latest_block.0)
+        .collect();
+    let block_hashes = chain
+        .get_block_hashes(&started, latest_block)
+        .await
+        .map_err(validate::block_hashes_error)?;
+    let proof_blocks: Vec<Option<BlockNum>> = stream::iter(0..started.len())
+        .map(|window_num| async move {
+            chain
+                .get_proof_block_num_from_window(deal_id, window_num as u64, latest_block)
+                .await
+                .map_err(|e| {
+                    ValidationError::RpcUnavailable(format!(
+                        "Could not get block where proof was submitted for window {window_num}: {e}"
+                    ))
+                })
+        })
+        .buffered(settings.window_concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    let windows = start_blocks
+        .into_iter()
+        .enumerate()
+        .map(|(window_num, start_block)| WindowSchedule {
+            window_num,
+            start_block,
+            end_block: BlockNum(start_block.0 + frequency - 1),
+            target_block_hash: block_hashes.get(&start_block.0).copied(),
+            proof_block: proof_blocks.get(window_num).copied().flatten(),
+        })
+        .collect();
+    Ok(DealInspection {
+        deal: DealEvidence::new(deal_id, &deal_info),
+        status,
+        deal_end_block,
+        cancellation_block,
+        latest_block,
+        current_window,
+        windows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::{DealInfo, MockChain};
+
+    fn deal(deal_start_block: u64, deal_length_in_blocks: u64, proof_frequency: u64) -> DealInfo {
+        DealInfo {
+            deal_start_block: BlockNum(deal_start_block),
+            deal_length_in_blocks: BlockNum(deal_length_in_blocks),
+            proof_frequency_in_blocks: BlockNum(proof_frequency),
+            file_size: 1024,
+            blake3_checksum: blake3::hash(b"banyan"),
+        }
+    }
+
+    #[tokio::test]
+    /// An active deal shows its current window, and only the windows that have started have a
+    /// target block hash or a proof.
+    async fn active_deal_schedule() -> Result<(), ValidationError> {
+        let mut chain = MockChain::new(104);
+        chain.add_deal(DealID(1), deal(100, 9, 3));
+        chain.add_proof(DealID(1), 0, BlockNum(101), Vec::new());
+
+        let inspection = inspect_deal(&chain, &ValidationSettings::default(), DealID(1)).await?;
+        assert_eq!(inspection.status, DealStatus::Active);
+        assert_eq!(inspection.current_window, Some(1));
+        let windows = inspection.windows;
+        assert_eq!(windows.len(), 3);
+        assert_eq!(
+            (windows[1].start_block, windows[1].end_block),
+            (BlockNum(103), BlockNum(105))
+        );
+        assert_eq!(windows[0].proof_block, Some(BlockNum(101)));
+        assert_eq!(windows[1].proof_block, None);
+        assert!(windows[1].target_block_hash.is_some());
+        assert!(windows[2].target_block_hash.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// If `getProofBlock` can't be read the inspection fails as an RPC outage, rather than
+    /// showing windows without proofs.
+    async fn unreadable_proof_blocks_fail_the_inspection() {
+        let mut chain = MockChain::new(104);
+        chain.add_deal(DealID(1), deal(100, 9, 3));
+        chain.fail("get_proof_block_num_from_window");
+
+        let error = inspect_deal(&chain, &ValidationSettings::default(), DealID(1))
+            .await
+            .expect_err("proof blocks can't be read");
+        assert!(matches!(error, ValidationError::RpcUnavailable(_)));
+        assert_eq!(error.http_status(), 503);
+    }
+}
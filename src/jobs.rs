@@ -0,0 +1,296 @@
+use crate::ChainlinkEARequest;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    /// Validation finished but the result hasn't reached the Chainlink node yet.
+    Done,
+    CallbackDelivered,
+    /// A synchronous request that was answered in its response, so there's nothing to deliver.
+    Answered,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobRecord {
+    pub request: ChainlinkEARequest,
+    pub state: JobState,
+    /// The envelope to PATCH back to the Chainlink node, once validation has finished.
+    pub response: Option<serde_json::Value>,
+    /// When the record was last written, in milliseconds since the Unix epoch. Records from
+    /// before it was kept read as 0.
+    #[serde(default)]
+    pub updated_at_ms: u64,
+}
+
+/// Validation jobs, persisted so a restart doesn't lose them and a retried request id can be
+/// answered from here. Keyed by the Chainlink request id.
+pub struct JobStore {
+    tree: sled::Tree,
+}
+
+impl JobStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let db = sled::open(path.as_ref())
+            .map_err(|e| anyhow!("Couldn't open job store at {:?}: {e}", path.as_ref()))?;
+        Ok(Self {
+            tree: db.open_tree("jobs")?,
+        })
+    }
+
+    /// A store that's deleted when dropped, for tests and one-off runs.
+    pub fn temporary() -> Result<Self> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Ok(Self {
+            tree: db.open_tree("jobs")?,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<JobRecord>> {
+        match self.tree.get(id)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes `record`, stamped with the current time, and flushes it to disk before returning.
+    pub fn put(&self, record: &JobRecord) -> Result<()> {
+        self.tree
+            .insert(record.request.id.as_str(), stamped(record)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Writes `record` only if no job with its id exists yet, atomically, so that concurrent
+    /// duplicates of a request don't both start a job. False if the id was already taken.
+    pub fn insert_new(&self, record: &JobRecord) -> Result<bool> {
+        let inserted = self
+            .tree
+            .compare_and_swap(
+                record.request.id.as_str(),
+                None as Option<&[u8]>,
+                Some(stamped(record)?),
+            )?
+            .is_ok();
+        self.tree.flush()?;
+        Ok(inserted)
+    }
+
+    /// Forgets the job, so a retry of the same request id validates again.
+    pub fn remove(&self, id: &str) -> Result<()> {
+        self.tree.remove(id)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    pub fn set_state(
+        &self,
+        id: &str,
+        state: JobState,
+        response: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let mut record = self
+            .get(id)?
+            .ok_or_else(|| anyhow!("No job with id {id}"))?;
+        record.state = state;
+        if response.is_some() {
+            record.response = response;
+        }
+        self.put(&record)
+    }
+
+    /// Every async job whose result hasn't been delivered yet, to resume on startup.
+    /// Synchronous requests are stored as `Answered`, so they're never unfinished even when the
+    /// request carried a `responseURL`.
+    pub fn unfinished(&self) -> Result<Vec<JobRecord>> {
+        let mut jobs = Vec::new();
+        for entry in self.tree.iter() {
+            let (_, bytes) = entry?;
+            let record: JobRecord = serde_json::from_slice(&bytes)?;
+            let finished = matches!(
+                record.state,
+                JobState::CallbackDelivered | JobState::Answered
+            );
+            if !finished && record.request.response_url.is_some() {
+                jobs.push(record);
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Forgets every job last written before `updated_before`, returning how many there were.
+    /// By then the Chainlink node has long stopped retrying the request id or waiting for its
+    /// result, so keeping them would only grow the store.
+    pub fn prune(&self, updated_before: SystemTime) -> Result<usize> {
+        let cutoff = millis_since_epoch(updated_before);
+        let mut pruned = 0;
+        for entry in self.tree.iter() {
+            let (id, bytes) = entry?;
+            let record: JobRecord = serde_json::from_slice(&bytes)?;
+            if record.updated_at_ms < cutoff {
+                self.tree.remove(id)?;
+                pruned += 1;
+            }
+        }
+        self.tree.flush()?;
+        Ok(pruned)
+    }
+}
+
+fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// `record` serialized with `updated_at_ms` set to now.
+fn stamped(record: &JobRecord) -> Result<Vec<u8>> {
+    let record = JobRecord {
+        updated_at_ms: millis_since_epoch(SystemTime::now()),
+        ..record.clone()
+    };
+    Ok(serde_json::to_vec(&record)?)
+}
+
+/// Outcome of `InFlight::claim`.
+pub enum Claim {
+    /// Nobody is working on this id; the caller must `finish` it.
+    New(watch::Sender<Option<serde_json::Value>>),
+    /// Another request with this id is running; wait on it for the result.
+    Existing(watch::Receiver<Option<serde_json::Value>>),
+}
+
+/// Synchronous requests currently being validated, so that a retry of the same id waits for the
+/// running validation instead of starting another one.
+#[derive(Default)]
+pub struct InFlight {
+    requests: Mutex<HashMap<String, watch::Receiver<Option<serde_json::Value>>>>,
+}
+
+impl InFlight {
+    pub fn claim(&self, id: &str) -> Claim {
+        let mut requests = self.requests.lock().expect("in-flight lock poisoned");
+        match requests.get(id) {
+            // a dropped sender means the first request died without finishing, so start over
+            Some(receiver) if receiver.has_changed().is_ok() => Claim::Existing(receiver.clone()),
+            _ => {
+                let (sender, receiver) = watch::channel(None);
+                requests.insert(id.to_string(), receiver);
+                Claim::New(sender)
+            }
+        }
+    }
+
+    /// Hands `body` to everyone waiting on `id` and forgets about it.
+    pub fn finish(
+        &self,
+        id: &str,
+        sender: watch::Sender<Option<serde_json::Value>>,
+        body: serde_json::Value,
+    ) {
+        self.requests
+            .lock()
+            .expect("in-flight lock poisoned")
+            .remove(id);
+        // nobody waiting is fine
+        let _ = sender.send(Some(body));
+    }
+}
+
+/// Waits for the request holding the `Claim::New` for this id to finish. None if it gave up
+/// without a result.
+pub async fn wait_for(
+    mut receiver: watch::Receiver<Option<serde_json::Value>>,
+) -> Option<serde_json::Value> {
+    loop {
+        if let Some(body) = receiver.borrow().clone() {
+            return Some(body);
+        }
+        receiver.changed().await.ok()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::ChainlinkRequestData;
+
+    fn record(id: &str, deal_id: &str) -> JobRecord {
+        JobRecord {
+            request: ChainlinkEARequest {
+                id: id.to_string(),
+                data: ChainlinkRequestData {
+                    deal_id: deal_id.to_string(),
+                    include_windows: false,
+                },
+                meta: None,
+                response_url: Some("http://localhost:6688/v2/resume/1".to_string()),
+            },
+            state: JobState::Queued,
+            response: None,
+            updated_at_ms: 0,
+        }
+    }
+
+    #[test]
+    fn only_the_first_insert_of_an_id_wins() {
+        let jobs = JobStore::temporary().unwrap();
+        assert!(jobs.insert_new(&record("1", "55378008")).unwrap());
+        assert!(!jobs.insert_new(&record("1", "42")).unwrap());
+        assert_eq!(
+            jobs.get("1").unwrap().unwrap().request.data.deal_id,
+            "55378008"
+        );
+
+        jobs.remove("1").unwrap();
+        assert!(jobs.get("1").unwrap().is_none());
+        assert!(jobs.insert_new(&record("1", "42")).unwrap());
+    }
+
+    #[test]
+    /// Only async jobs still owing a callback are resumed, not answered sync requests that
+    /// happened to carry a `responseURL`.
+    fn answered_jobs_are_not_unfinished() {
+        let jobs = JobStore::temporary().unwrap();
+        for (id, state) in [
+            ("1", JobState::Queued),
+            ("2", JobState::Done),
+            ("3", JobState::CallbackDelivered),
+            ("4", JobState::Answered),
+        ] {
+            jobs.put(&JobRecord {
+                state,
+                ..record(id, "55378008")
+            })
+            .unwrap();
+        }
+        let mut unfinished: Vec<String> = jobs
+            .unfinished()
+            .unwrap()
+            .into_iter()
+            .map(|record| record.request.id)
+            .collect();
+        unfinished.sort();
+        assert_eq!(unfinished, vec!["1", "2"]);
+    }
+
+    #[test]
+    fn prune_forgets_only_old_jobs() {
+        let jobs = JobStore::temporary().unwrap();
+        jobs.put(&record("old", "55378008")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let cutoff = SystemTime::now();
+        jobs.put(&record("new", "55378008")).unwrap();
+
+        assert_eq!(jobs.prune(cutoff).unwrap(), 1);
+        assert!(jobs.get("old").unwrap().is_none());
+        assert!(jobs.get("new").unwrap().is_some());
+    }
+}
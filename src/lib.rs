@@ -0,0 +1,91 @@
+//! Validates Banyan storage deals for the Chainlink external adapter.
+//!
+//! `validate` is the validation engine, run against any `chain::DealChain`. The Rocket server in
+//! `main.rs` is a thin layer on top, so other services can link the validator directly.
+//!
+//! Cargo features, all on by default:
+//! - `server`: the Rocket adapter binary, its `config`, and the modules only it uses: `health`,
+//!   `inspect` and `proof_check`. Turns on `persistence`, `async-callback` and `metrics`.
+//! - `persistence`: the sled-backed `jobs` store and on-disk `cache` results.
+//! - `async-callback`: `callback`, which PATCHes async results back to the Chainlink node.
+//! - `metrics`: the Prometheus `metrics` that validations and `EthChain` reads are recorded in.
+//! - `client`: `client`, the helper for calling a running adapter.
+//!
+//! With none of them, the crate is the validation engine and the `DealChain` trait.
+#![deny(unused_crate_dependencies)]
+
+pub mod blocks;
+pub mod cache;
+#[cfg(feature = "async-callback")]
+pub mod callback;
+pub mod chain;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "server")]
+pub mod config;
+pub mod contract;
+pub mod error;
+pub mod evidence;
+#[cfg(feature = "server")]
+pub mod health;
+#[cfg(feature = "server")]
+pub mod inspect;
+#[cfg(feature = "persistence")]
+pub mod jobs;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "server")]
+pub mod proof_check;
+pub mod validate;
+
+use serde::{Deserialize, Serialize};
+// only used by the server binary
+#[cfg(feature = "server")]
+use dotenv as _;
+#[cfg(feature = "server")]
+use rocket as _;
+use tokio as _;
+#[cfg(feature = "server")]
+use tracing_subscriber as _;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainlinkEARequest {
+    pub id: String,
+    pub data: validate::ChainlinkRequestData,
+    pub meta: Option<serde_json::Value>,
+    /// Where to PATCH the result in async mode. Chainlink sends this as `responseURL`.
+    #[serde(rename = "responseURL", alias = "response_url", default)]
+    pub response_url: Option<String>,
+}
+
+/// Success envelope for a Chainlink bridge task. The job pipeline reads the fields of `data`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChainlinkEAResponse {
+    #[serde(rename = "jobRunID")]
+    pub job_run_id: String,
+    pub data: validate::ResponseData,
+    pub result: String,
+    #[serde(rename = "statusCode")]
+    pub status_code: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChainlinkEAErrorDetail {
+    pub name: String,
+    pub message: String,
+    /// The `ValidationError::status` of the failure, when validation got that far.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<u16>,
+}
+
+/// Error envelope for a Chainlink bridge task, shown in the job run UI.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChainlinkEAError {
+    /// None when the request couldn't be parsed far enough to find its id.
+    #[serde(rename = "jobRunID")]
+    pub job_run_id: Option<String>,
+    pub status: String,
+    pub error: ChainlinkEAErrorDetail,
+    #[serde(rename = "statusCode")]
+    pub status_code: u16,
+}
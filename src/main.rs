@@ -1,566 +1,1134 @@
-#![deny(unused_crate_dependencies)]
-
-//use rust_chainlink_ea_api::validate::*;
-pub mod validate;
-
 use anyhow::Result;
-use banyan_shared::{eth::EthClient, types::DealID};
-use ethers as _;
-use rand::Rng;
-use rocket::serde::{json::serde_json, json::Json, Deserialize, Serialize};
+use chainlink_proof_validator::blocks::BlockHashFetcher;
+use chainlink_proof_validator::cache::ResultCache;
+use chainlink_proof_validator::callback::CallbackClient;
+use chainlink_proof_validator::chain::DealChain;
+use chainlink_proof_validator::config::{CheckProofArgs, Cli, Command, Config};
+use chainlink_proof_validator::error::ValidationError;
+use chainlink_proof_validator::evidence::EvidenceBundle;
+use chainlink_proof_validator::health::{self, ReadinessProbe};
+use chainlink_proof_validator::inspect::{self, DealInspection};
+use chainlink_proof_validator::jobs::{self, Claim, InFlight, JobRecord, JobState, JobStore};
+use chainlink_proof_validator::metrics::METRICS;
+use chainlink_proof_validator::proof_check::{self, ChunkRange, ProofCheck};
+use chainlink_proof_validator::{
+    validate, ChainlinkEAError, ChainlinkEAErrorDetail, ChainlinkEARequest, ChainlinkEAResponse,
+};
+use clap::Parser;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::{json::serde_json, json::Json};
 use rocket::tokio::task::spawn;
-use rocket::{post, State};
+use rocket::tokio::time::sleep;
+use rocket::{catch, get, post, Build, Request, Rocket, State};
+use std::path::Path;
 use std::sync::Arc;
-use tokio as _;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, instrument, warn};
+use tracing_subscriber::EnvFilter;
 
 pub struct WebserverState {
-    pub provider: Arc<EthClient>,
+    pub chain: Arc<dyn DealChain>,
+    /// What `/ready` and `/version` read, from the same endpoint as `chain`.
+    pub probe: Arc<dyn ReadinessProbe>,
+    pub cache: Arc<ResultCache>,
+    pub settings: validate::ValidationSettings,
+    pub callback: Arc<CallbackClient>,
+    pub jobs: Arc<JobStore>,
+    pub in_flight: InFlight,
     pub should_be_async: bool,
+    /// Chain the endpoint has to be on, if configured. Checked by `/ready`.
+    pub chain_id: Option<u64>,
+    pub max_block_age: Duration,
+}
+
+type EAResponse = (Status, Json<serde_json::Value>);
+
+/// Tokens a caller can present to use the adapter. Several can be valid at once so the bridge's
+/// token can be rotated without downtime. Empty means no authentication.
+pub struct BridgeTokens(Vec<blake3::Hash>);
+
+impl BridgeTokens {
+    pub fn new(tokens: &[String]) -> Self {
+        Self(
+            tokens
+                .iter()
+                .map(|token| blake3::hash(token.as_bytes()))
+                .collect(),
+        )
+    }
+
+    /// Compares digests, which `blake3::Hash` does in constant time, so a mismatch doesn't leak
+    /// how much of a token was right.
+    fn accepts(&self, token: &str) -> bool {
+        let presented = blake3::hash(token.as_bytes());
+        self.0.iter().any(|accepted| *accepted == presented)
+    }
+}
+
+/// Request guard for routes that cost RPC quota or expose results. Passes requests with an
+/// `Authorization: Bearer <token>` header carrying one of the `BridgeTokens`, and fails the rest
+/// with a 401, answered by `unauthorized_catcher`.
+pub struct BridgeAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BridgeAuth {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let tokens = match request.rocket().state::<BridgeTokens>() {
+            Some(tokens) if !tokens.0.is_empty() => tokens,
+            _ => return Outcome::Success(BridgeAuth),
+        };
+        let presented = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(token) if tokens.accepts(token.trim()) => Outcome::Success(BridgeAuth),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ChainlinkEARequest {
-    pub id: String,
-    pub data: validate::ChainlinkRequestData,
-    pub meta: Option<serde_json::Value>,
-    pub response_url: Option<String>,
+fn error_response(
+    job_run_id: Option<String>,
+    status: Status,
+    name: &str,
+    message: String,
+    code: Option<u16>,
+) -> EAResponse {
+    let error = ChainlinkEAError {
+        job_run_id,
+        status: "errored".to_string(),
+        error: ChainlinkEAErrorDetail {
+            name: name.to_string(),
+            message,
+            code,
+        },
+        status_code: status.code,
+    };
+    (status, Json(serde_json::json!(error)))
+}
+
+fn success_response(job_run_id: &str, data: validate::ResponseData) -> EAResponse {
+    let envelope = ChainlinkEAResponse {
+        job_run_id: job_run_id.to_string(),
+        result: data.result.clone(),
+        data,
+        status_code: Status::Ok.code,
+    };
+    (Status::Ok, Json(serde_json::json!(envelope)))
 }
 
 fn format_response(
-    result: Result<validate::ChainlinkResponse, anyhow::Error>,
-) -> Json<serde_json::Value> {
+    job_run_id: &str,
+    result: Result<validate::ChainlinkResponse, ValidationError>,
+) -> EAResponse {
     match result {
-        Ok(data) => Json(serde_json::json!(data)),
-        Err(e) => Json(serde_json::json!({"error": e.to_string()})),
+        Ok(response) => success_response(job_run_id, response.data),
+        Err(e) => match (e.http_status(), e.deal_id()) {
+            // final answers about the deal go on-chain like a score would
+            (200, Some(deal_id)) => {
+                success_response(job_run_id, validate::ResponseData::from_error(deal_id, &e))
+            }
+            (code, _) => error_response(
+                Some(job_run_id.to_string()),
+                Status::from_code(code).unwrap_or(Status::InternalServerError),
+                e.name(),
+                e.to_string(),
+                Some(e.status()),
+            ),
+        },
+    }
+}
+
+/// Keeps Rocket's own failures (e.g. a body that isn't a `ChainlinkEARequest`) in the same envelope.
+#[catch(default)]
+pub fn default_catcher(status: Status, _request: &Request) -> EAResponse {
+    error_response(
+        None,
+        status,
+        "InvalidRequest",
+        status.reason_lossy().to_string(),
+        None,
+    )
+}
+
+/// Requests without a valid bridge token never reach the route, so there's no `jobRunID` to echo.
+#[catch(401)]
+pub fn unauthorized_catcher(_request: &Request) -> EAResponse {
+    error_response(
+        None,
+        Status::Unauthorized,
+        "Unauthorized",
+        "Missing or invalid bridge token".to_string(),
+        None,
+    )
+}
+
+/// Runs an async job through to delivering its result, picking up where a restart left it.
+#[instrument(
+    skip_all,
+    fields(job_run_id = %record.request.id, deal_id = %record.request.data.deal_id)
+)]
+async fn run_job(
+    chain: Arc<dyn DealChain>,
+    cache: Arc<ResultCache>,
+    settings: validate::ValidationSettings,
+    callback: Arc<CallbackClient>,
+    jobs: Arc<JobStore>,
+    record: JobRecord,
+) {
+    let job_run_id = record.request.id.clone();
+    let response_url = match record.request.response_url.clone() {
+        Some(response_url) => response_url,
+        None => {
+            error!("Job {job_run_id} has no responseURL to deliver to");
+            return;
+        }
+    };
+    METRICS.async_jobs_pending.inc();
+    let (status, body) = match record.response {
+        // validated before a restart, only the callback is outstanding
+        Some(body) if record.state == JobState::Done => (Status::Ok, body),
+        _ => {
+            if let Err(e) = jobs.set_state(&job_run_id, JobState::Running, None) {
+                error!("Couldn't mark job {job_run_id} as running: {e}");
+            }
+            let result = validate::validate_deal_internal(
+                chain.as_ref(),
+                Some(cache.as_ref()),
+                &settings,
+                record.request.data,
+            )
+            .await;
+            let (status, body) = format_response(&job_run_id, result);
+            // only keep answers, like the sync path does
+            if status == Status::Ok {
+                if let Err(e) = jobs.set_state(&job_run_id, JobState::Done, Some(body.0.clone())) {
+                    error!("Couldn't store result of job {job_run_id}: {e}");
+                }
+            }
+            (status, body.into_inner())
+        }
+    };
+    // send the result to the chainlink node; failures are logged by deliver
+    let delivered = callback
+        .deliver(&job_run_id, &response_url, &body)
+        .await
+        .is_ok();
+    if status != Status::Ok {
+        if let Err(e) = jobs.remove(&job_run_id) {
+            error!("Couldn't forget failed job {job_run_id}: {e}");
+        }
+    } else if delivered {
+        if let Err(e) = jobs.set_state(&job_run_id, JobState::CallbackDelivered, None) {
+            error!("Couldn't mark job {job_run_id} as delivered: {e}");
+        }
     }
+    METRICS.async_jobs_pending.dec();
+}
+
+/// Replays a response stored in the job store, with the HTTP status it was first sent with.
+fn stored_response(body: serde_json::Value) -> EAResponse {
+    let status = body["statusCode"]
+        .as_u64()
+        .and_then(|code| Status::from_code(code as u16))
+        .unwrap_or(Status::Ok);
+    (status, Json(body))
+}
+
+/// Answers a request id that's already in the job store. None if the request has to be
+/// validated after all.
+fn replay_job(
+    existing: Option<JobRecord>,
+    request: &ChainlinkEARequest,
+    should_be_async: bool,
+) -> Option<EAResponse> {
+    let existing = existing?;
+    // request ids are only random u16s, so a different deal under a known id isn't a retry
+    if existing.request.data != request.data {
+        return Some(error_response(
+            Some(request.id.clone()),
+            Status::Conflict,
+            "RequestIdReused",
+            format!(
+                "Request id {} was already used for deal {}",
+                request.id, existing.request.data.deal_id
+            ),
+            None,
+        ));
+    }
+    match existing.response {
+        Some(body) => Some(stored_response(body)),
+        // an async job that's still running delivers its result through its own callback
+        None if should_be_async => Some(pending_response(request.id.clone())),
+        None => None,
+    }
+}
+
+fn pending_response(job_run_id: String) -> EAResponse {
+    (
+        Status::Ok,
+        Json(serde_json::json!({
+            "jobRunID": job_run_id,
+            "pending": true
+        })),
+    )
 }
 
-// TODO prefix all logs with ID from request
+/// Everything logged while handling a request is tagged with its job run id and deal id, so one
+/// Chainlink job run can be followed through the logs, including its async job.
 #[post("/compute", format = "json", data = "<input_data>")]
+#[instrument(
+    skip_all,
+    fields(job_run_id = %input_data.id, deal_id = %input_data.data.deal_id)
+)]
 pub async fn compute(
+    _auth: BridgeAuth,
     webserver_state: &State<WebserverState>,
     input_data: Json<ChainlinkEARequest>,
-) -> Json<serde_json::Value> {
+) -> EAResponse {
+    let request = input_data.into_inner();
+    let job_run_id = request.id.clone();
+
+    // Chainlink nodes retry bridge calls, so a request id we've seen before is answered from
+    // what we already have rather than validating the deal again
+    let existing = webserver_state.jobs.get(&job_run_id).unwrap_or_else(|e| {
+        error!("Couldn't look up job {job_run_id}: {e}");
+        None
+    });
+    if let Some(response) = replay_job(existing, &request, webserver_state.should_be_async) {
+        return response;
+    }
+
     if webserver_state.should_be_async {
-        let new_provider = webserver_state.provider.clone();
-        spawn(async move {
-            let result =
-                validate::validate_deal_internal(new_provider, input_data.data.clone()).await;
-            // send the result to the chainlink node
-            reqwest::Client::new()
-                .patch(input_data.into_inner().response_url.unwrap())
-                .body(format_response(result).to_string())
-                .send()
-                .await
-                .unwrap();
-        });
-        Json(serde_json::json!({
-            "pending": true
-        }))
+        // without somewhere to send the result, accepting the job would just lose it
+        if request.response_url.is_none() {
+            return error_response(
+                Some(job_run_id),
+                Status::BadRequest,
+                "MissingResponseURL",
+                "Async mode needs a responseURL to deliver the result to".to_string(),
+                None,
+            );
+        }
+        let record = JobRecord {
+            request,
+            state: JobState::Queued,
+            response: None,
+            updated_at_ms: 0,
+        };
+        // the job only counts as accepted once it's on disk, and only the first of several
+        // concurrent duplicates gets to queue it
+        match webserver_state.jobs.insert_new(&record) {
+            Ok(true) => {}
+            Ok(false) => {
+                let existing = webserver_state.jobs.get(&job_run_id).ok().flatten();
+                return replay_job(existing, &record.request, true)
+                    .unwrap_or_else(|| pending_response(job_run_id));
+            }
+            Err(e) => {
+                return error_response(
+                    Some(job_run_id),
+                    Status::InternalServerError,
+                    "JobStoreError",
+                    format!("Couldn't queue job: {e}"),
+                    None,
+                )
+            }
+        }
+        spawn(run_job(
+            webserver_state.chain.clone(),
+            webserver_state.cache.clone(),
+            webserver_state.settings.clone(),
+            webserver_state.callback.clone(),
+            webserver_state.jobs.clone(),
+            record,
+        ));
+        pending_response(job_run_id)
         // end of thread
     } else {
-        format_response(
+        // a retry waits on the running validation, but the same id for another deal doesn't
+        let in_flight_key = format!("{job_run_id}:{}", request.data.deal_id);
+        let sender = match webserver_state.in_flight.claim(&in_flight_key) {
+            Claim::New(sender) => sender,
+            Claim::Existing(receiver) => {
+                return match jobs::wait_for(receiver).await {
+                    Some(body) => stored_response(body),
+                    None => error_response(
+                        Some(job_run_id),
+                        Status::ServiceUnavailable,
+                        "RetryInterrupted",
+                        "The earlier request with this id stopped without a result".to_string(),
+                        None,
+                    ),
+                }
+            }
+        };
+        let (status, body) = format_response(
+            &job_run_id,
             validate::validate_deal_internal(
-                webserver_state.provider.clone(),
-                input_data.data.clone(),
+                webserver_state.chain.as_ref(),
+                Some(webserver_state.cache.as_ref()),
+                &webserver_state.settings,
+                request.data.clone(),
             )
             .await,
-        )
+        );
+        // only keep answers; a failed validation should be retried for real
+        if status == Status::Ok {
+            let record = JobRecord {
+                request,
+                state: JobState::Answered,
+                response: Some(body.0.clone()),
+                updated_at_ms: 0,
+            };
+            if let Err(e) = webserver_state.jobs.put(&record) {
+                error!("Couldn't store result of job {job_run_id}: {e}");
+            }
+        }
+        webserver_state
+            .in_flight
+            .finish(&in_flight_key, sender, body.0.clone());
+        (status, body)
     }
 }
 
-#[rocket::main]
-async fn main() -> Result<()> {
-    dotenv::dotenv().ok();
-    let should_be_async = std::env::var("SHOULD_BE_ASYNC")
-        .map_or_else(|_| false, |n| n.parse::<bool>().unwrap_or(false));
-
-    // create an ethers HTTP provider
-    let eth_client = Arc::new(EthClient::default());
-
-    let _ = rocket::build()
-        .mount("/", rocket::routes![compute])
-        .manage(WebserverState {
-            provider: eth_client,
-            should_be_async,
-        })
-        .launch()
-        .await?;
-
-    Ok(())
+/// Same validation as `/compute`, but always answers synchronously and always includes the
+/// per-window breakdown, for explaining a result rather than posting it on-chain.
+#[post("/report", format = "json", data = "<input_data>")]
+#[instrument(
+    skip_all,
+    fields(job_run_id = %input_data.id, deal_id = %input_data.data.deal_id)
+)]
+pub async fn report(
+    _auth: BridgeAuth,
+    webserver_state: &State<WebserverState>,
+    input_data: Json<ChainlinkEARequest>,
+) -> EAResponse {
+    let mut request_data = input_data.data.clone();
+    request_data.include_windows = true;
+    format_response(
+        &input_data.id,
+        validate::validate_deal_internal(
+            webserver_state.chain.as_ref(),
+            Some(webserver_state.cache.as_ref()),
+            &webserver_state.settings,
+            request_data,
+        )
+        .await,
+    )
 }
 
-/// Helper function for testing inputs to Chainlink EA without having to run a node.
-pub async fn rust_chainlink_ea_api_call(
-    deal_id: DealID,
-    api_url: String,
-) -> Result<validate::ChainlinkResponse, anyhow::Error> {
-    // Job id when chainlink calls is not random.
-    let mut rng = rand::thread_rng();
-    let random_job_id: u16 = rng.gen();
-    let map = serde_json::json!({
-        "id": random_job_id.to_string(),
-        "data":
-        {
-             "deal_id": deal_id.0.to_string()
-        }
-    });
-    let client = reqwest::Client::new();
-    let res = client
-        .post(api_url)
-        .json(&map)
-        .send()
-        .await?
-        .json::<validate::ChainlinkResponse>()
-        .await?;
-    dbg!("{:?}", &res);
-    Ok(res)
-}
-/*
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use banyan_shared::{
-        deals::DealProposalBuilder,
-        eth::EthClient,
-        types::{BlockNum, DealID, DealProposal},
-    };
-    use ethers::types::Bytes;
-    use std::fs::File;
-
-    #[tokio::test]
-    /// This test will fail if no deal has ever been created on the contract, or if that deal has valid proofs in it.
-    async fn api_call_test() -> Result<(), anyhow::Error> {
-        let response_data: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(DealID(1), "http://127.0.0.1:8000/compute".to_string())
-                .await?;
-        assert_eq!(response_data.data.success_count, 1);
-        Ok(())
+/// Validates like `/report`, bypassing the cache, and answers with the evidence bundle the
+/// verdict can be re-checked from offline with `verify-bundle`.
+#[post("/evidence", format = "json", data = "<input_data>")]
+#[instrument(
+    skip_all,
+    fields(job_run_id = %input_data.id, deal_id = %input_data.data.deal_id)
+)]
+pub async fn evidence_bundle(
+    _auth: BridgeAuth,
+    webserver_state: &State<WebserverState>,
+    input_data: Json<ChainlinkEARequest>,
+) -> EAResponse {
+    match validate::collect_evidence(
+        webserver_state.chain.as_ref(),
+        &webserver_state.settings,
+        input_data.data.clone(),
+    )
+    .await
+    {
+        Ok(bundle) => (Status::Ok, Json(serde_json::json!(bundle))),
+        Err(e) => format_response(&input_data.id, Err(e)),
     }
 }
-    /
-    #[tokio::test]
-    /// This tests verifies that a deal with no logged proofs will have a success count of 0
-    async fn api_no_proofs_test() -> Result<(), anyhow::Error> {
-        let file = File::open("test_files/ethereum.pdf").unwrap();
-        let deal_proposal = DealProposal::builder().with_file(file).build().unwrap();
-        let eth_client = EthClient::default();
-
-        let deal_id: DealID = eth_client
-            .propose_deal(deal_proposal, None, None)
-            .await
-            .expect("Failed to send deal proposal");
-
-        dbg!("Offer Created for Deal ID: {:}", deal_id);
-        let deal = eth_client.get_offer(deal_id).await.unwrap();
-        // Assert that the deal we read is the same as the one we sent
-        dbg!("deal {:?}", deal.clone());
-        assert_eq!(deal.deal_length_in_blocks, BlockNum(10));
-
-        let response_data: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
-        assert_eq!(response_data.data.success_count, 0);
-        Ok(())
-    }
-
-    #[tokio::test]
-    /// This test verifies that an eth client can create multiple consecutive deals, which all have no logged proofs.
-    async fn multiple_deals_same_file_no_proofs() -> Result<(), anyhow::Error> {
-        let file = File::open("test_files/ethereum.pdf").unwrap();
-        let deal_proposal = DealProposal::builder().with_file(file).build().unwrap();
-        let eth_client = EthClient::default();
-
-        let deal_id: DealID = eth_client
-            .propose_deal(deal_proposal.clone(), None, None)
-            .await
-            .expect("Failed to send deal proposal");
-
-        dbg!("Proof Created for Deal ID: {:}", &deal_id);
-        let deal = eth_client.get_offer(deal_id).await.unwrap();
-        // Assert that the deal we read is the same as the one we sent
-        assert_eq!(deal.deal_length_in_blocks, BlockNum(10));
-
-        let deal_id_2: DealID = eth_client
-            .propose_deal(deal_proposal, None, None)
-            .await
-            .expect("Failed to send deal proposal");
-        dbg!("Proof Created for Deal ID: {:}", &deal_id_2);
-        let deal = eth_client.get_offer(deal_id_2).await.unwrap();
-        assert_eq!(deal.deal_length_in_blocks, BlockNum(10));
-
-        let response_data_1: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
-        assert_eq!(response_data_1.data.success_count, 0);
-        let response_data_2: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id_2, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
-        assert_eq!(response_data_2.data.success_count, 0);
-        Ok(())
-    }
-
-    #[tokio::test]
-    /// This test verifies that we can create a deal with one window, submit a proof, and verify it.
-    async fn deal_and_proof_one_window() -> Result<(), anyhow::Error> {
-        let mut file = File::open("test_files/ethereum.pdf").unwrap();
-
-        let deal_proposal: DealProposal = DealProposalBuilder::new(
-            "0x0000000000000000000000000000000000000000".to_string(),
-            3,
-            3,
-            0.0,
-            0.0,
-            "0x0000000000000000000000000000000000000000".to_string(),
-        )
-        .with_file(file.try_clone()?)
-        .build()
-        .unwrap();
-
-        let eth_client = EthClient::default();
 
-        let deal_id: DealID = eth_client
-            .propose_deal(deal_proposal.clone(), None, None)
-            .await
-            .expect("Failed to send deal proposal");
+/// State of an async job, and its result once validation has finished.
+#[get("/jobs/<id>")]
+#[instrument(skip_all, fields(job_run_id = %id))]
+pub fn job_status(
+    _auth: BridgeAuth,
+    webserver_state: &State<WebserverState>,
+    id: &str,
+) -> EAResponse {
+    match webserver_state.jobs.get(id) {
+        Ok(Some(record)) => (Status::Ok, Json(serde_json::json!(record))),
+        Ok(None) => error_response(
+            Some(id.to_string()),
+            Status::NotFound,
+            "JobNotFound",
+            format!("No job with id {id}"),
+            None,
+        ),
+        Err(e) => error_response(
+            Some(id.to_string()),
+            Status::InternalServerError,
+            "JobStoreError",
+            e.to_string(),
+            None,
+        ),
+    }
+}
 
-        let deal = eth_client.get_offer(deal_id).await.unwrap();
+/// Liveness: answers as long as the process is serving requests, without touching the chain.
+#[get("/health")]
+pub fn health_check() -> EAResponse {
+    (Status::Ok, Json(serde_json::json!({ "status": "up" })))
+}
 
-        // create a proof using the same file we used to create the deal
-        let (_hash, proof) = eth_client
-            .create_proof_helper(
-                deal.deal_start_block,
-                &mut file,
-                deal.file_size.as_u64(),
-                true,
-            )
-            .await
-            .expect("Failed to create proof");
-
-        // Wait one block until current block is no longer the deal start block
-        let mut current_block_num = deal.deal_start_block;
-        while current_block_num == deal.deal_start_block {
-            current_block_num = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
-        }
+/// Readiness: 200 if the adapter can validate deals right now, 503 otherwise, with each check's
+/// outcome either way.
+#[get("/ready")]
+pub async fn ready(webserver_state: &State<WebserverState>) -> EAResponse {
+    let readiness = health::check_readiness(
+        webserver_state.probe.as_ref(),
+        webserver_state.chain_id,
+        webserver_state.max_block_age,
+    )
+    .await;
+    let status = Status::from_code(readiness.http_status()).unwrap_or(Status::ServiceUnavailable);
+    (status, Json(serde_json::json!(readiness)))
+}
 
-        let target_block = deal.deal_start_block;
-        let _block_num: BlockNum = eth_client
-            .post_proof(deal_id, proof, target_block, None, None)
-            .await
-            .expect("Failed to post proof");
-
-        let mut current_block_num_2 = BlockNum(0);
-        while !EthClient::deal_over(current_block_num_2, deal.clone()) {
-            current_block_num_2 = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
-        }
+/// What's running, and against which contract. `chain_id` is what the RPC endpoint reports,
+/// null if it can't be reached.
+#[get("/version")]
+pub async fn version(webserver_state: &State<WebserverState>) -> EAResponse {
+    let chain_id = webserver_state.probe.chain_id().await.ok();
+    (
+        Status::Ok,
+        Json(serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "git_commit": env!("GIT_COMMIT"),
+            "contract_address": webserver_state.probe.contract_address(),
+            "chain_id": chain_id,
+        })),
+    )
+}
 
-        let response_data: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
+/// Prometheus scrape target.
+#[get("/metrics")]
+pub fn metrics() -> (ContentType, String) {
+    (ContentType::Plain, METRICS.gather())
+}
 
-        assert_eq!(response_data.data.success_count, 1);
-        Ok(())
+/// Re-runs the validation recorded in the bundle at `path` without any chain access, prints
+/// the recomputed response, and fails if it differs from the recorded one.
+fn verify_bundle(path: &Path) -> Result<()> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Couldn't open bundle {:?}: {e}", path))?;
+    let bundle: EvidenceBundle = serde_json::from_reader(std::io::BufReader::new(file))?;
+    let recomputed = bundle.verify()?;
+    println!("{}", serde_json::to_string_pretty(&recomputed)?);
+    if serde_json::to_value(&recomputed)? != serde_json::to_value(&bundle.response)? {
+        return Err(anyhow::anyhow!(
+            "Recomputed response differs from the one recorded in {:?}",
+            path
+        ));
     }
+    info!("Bundle {:?} reproduces its recorded response", path);
+    Ok(())
+}
 
-    #[tokio::test]
-    /// This test verifies that we can create a deal with two window, post one proof and simply not post a second, and recieve
-    /// A success count of 1.
-    async fn one_proof_window_missing() -> Result<(), anyhow::Error> {
-        let mut file = File::open("test_files/ethereum.pdf").unwrap();
-
-        let deal_proposal: DealProposal = DealProposalBuilder::new(
-            "0x0000000000000000000000000000000000000000".to_string(),
-            6,
-            3,
-            0.0,
-            0.0,
-            "0x0000000000000000000000000000000000000000".to_string(),
-        )
-        .with_file(file.try_clone()?)
-        .build()
-        .unwrap();
-        let eth_client = EthClient::default();
-
-        let deal_id: DealID = eth_client
-            .propose_deal(deal_proposal.clone(), None, None)
-            .await
-            .expect("Failed to send deal proposal");
+/// Validates a deal in-process, like `/report` but uncached, and prints the response with its
+/// per-window breakdown, as a table or as JSON.
+async fn validate_command(config: &Config, deal_id: u64, json: bool) -> Result<()> {
+    let chain = config.connect().await?;
+    let response = validate::validate_deal_internal(
+        &chain,
+        None,
+        &config.validation_settings(),
+        validate::ChainlinkRequestData {
+            deal_id: deal_id.to_string(),
+            include_windows: true,
+        },
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("{}: {e}", e.name()))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+    } else {
+        print_response(&response.data);
+    }
+    Ok(())
+}
 
-        let deal = eth_client.get_offer(deal_id).await.unwrap();
+fn print_response(data: &validate::ResponseData) {
+    println!("deal:          {}", data.deal_id.0);
+    println!("result:        {}", data.result);
+    println!("status:        {}", data.status);
+    println!(
+        "valid windows: {} of {}",
+        data.success_count, data.num_windows
+    );
+    if let (Some(block), Some(hash)) = (data.pinned_block, data.pinned_block_hash) {
+        println!("pinned block:  {} ({hash:?})", block.0);
+    }
+    let windows = match &data.windows {
+        Some(windows) if !windows.is_empty() => windows,
+        _ => return,
+    };
+    println!();
+    println!(
+        "{:>6}  {:>12}  {:>12}  {:>9}  {:>12}  {:>10}  verdict",
+        "window", "target block", "proof block", "proof len", "chunk offset", "chunk size"
+    );
+    for window in windows {
+        let proof_block = window
+            .proof_block
+            .map_or_else(|| "-".to_string(), |block| block.0.to_string());
+        let proof_len = window
+            .proof_len
+            .map_or_else(|| "-".to_string(), |len| len.to_string());
+        let verdict = serde_json::to_value(window.verdict)
+            .ok()
+            .and_then(|verdict| verdict.as_str().map(str::to_string))
+            .unwrap_or_default();
+        println!(
+            "{:>6}  {:>12}  {:>12}  {:>9}  {:>12}  {:>10}  {verdict}",
+            window.window_num,
+            window.target_block.0,
+            proof_block,
+            proof_len,
+            window.chunk_offset,
+            window.chunk_size
+        );
+    }
+}
 
-        let target_window: usize = eth_client
-            .compute_target_window(deal.deal_start_block, deal.proof_frequency_in_blocks)
-            .await
-            .expect("Failed to compute target window");
+/// Prints a deal's parameters and proof window schedule, as a table or as JSON.
+async fn inspect_command(config: &Config, deal_id: u64, json: bool) -> Result<()> {
+    let chain = config.connect().await?;
+    let inspection = inspect::inspect_deal(
+        &chain,
+        &config.validation_settings(),
+        banyan_shared::types::DealID(deal_id),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("{}: {e}", e.name()))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&inspection)?);
+    } else {
+        print_inspection(&inspection);
+    }
+    Ok(())
+}
 
-        let target_block = EthClient::compute_target_block_start(
-            deal.deal_start_block,
-            deal.proof_frequency_in_blocks,
-            target_window,
+fn print_inspection(inspection: &DealInspection) {
+    let deal = &inspection.deal;
+    println!("deal:               {}", deal.deal_id.0);
+    println!("status:             {:?}", inspection.status);
+    println!("start block:        {}", deal.deal_start_block.0);
+    println!("end block:          {}", inspection.deal_end_block.0);
+    println!("length in blocks:   {}", deal.deal_length_in_blocks.0);
+    println!("proof frequency:    {}", deal.proof_frequency_in_blocks.0);
+    println!("file size:          {}", deal.file_size);
+    println!("blake3 checksum:    {}", deal.blake3_checksum);
+    if let Some(block) = inspection.cancellation_block {
+        println!("cancelled at block: {}", block.0);
+    }
+    println!("latest block:       {}", inspection.latest_block.0);
+    if let Some(window_num) = inspection.current_window {
+        println!("current window:     {window_num}");
+    }
+    println!();
+    println!(
+        "  {:>6}  {:>12}  {:>12}  {:<66}  proof block",
+        "window", "start block", "end block", "target block hash"
+    );
+    for window in &inspection.windows {
+        let marker = if inspection.current_window == Some(window.window_num) {
+            '*'
+        } else {
+            ' '
+        };
+        let target_block_hash = window
+            .target_block_hash
+            .map_or_else(|| "-".to_string(), |hash| format!("{hash:?}"));
+        let proof_block = window
+            .proof_block
+            .map_or_else(|| "-".to_string(), |block| block.0.to_string());
+        println!(
+            "{marker} {:>6}  {:>12}  {:>12}  {:<66}  {proof_block}",
+            window.window_num, window.start_block.0, window.end_block.0, target_block_hash
         );
+    }
+}
 
-        // Wait one block until current block is no longer the deal start block
-        let mut current_block_num = deal.deal_start_block;
-        while current_block_num == deal.deal_start_block {
-            current_block_num = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
+/// Checks a proof against the original file and prints the expected and supplied chunks with
+/// the verdict. Only touches the chain to look up the target block hash from its number.
+async fn check_proof_command(cli: &Cli, args: &CheckProofArgs) -> Result<()> {
+    let target_block_hash = match (args.block_hash, args.block_number) {
+        (Some(block_hash), _) => block_hash,
+        (None, Some(block_number)) => {
+            let config = Config::resolve(cli)?;
+            if config.rpc_url.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "rpc_url must be set (--rpc-url or ETH_RPC_URL) to fetch block {block_number}"
+                ));
+            }
+            let block_hashes = BlockHashFetcher::new(
+                config.rpc_url.clone(),
+                config.block_hash_batch_size,
+                config.block_hash_cache_size,
+                config.confirmation_depth,
+            )?;
+            block_hashes
+                .get_block_hash(banyan_shared::types::BlockNum(block_number))
+                .await?
         }
+        (None, None) => unreachable!("clap requires --block-hash or --block-number"),
+    };
+    let proof = match (&args.proof, &args.proof_file) {
+        (Some(proof), _) => proof.to_vec(),
+        (None, Some(proof_file)) => std::fs::read(proof_file)
+            .map_err(|e| anyhow::anyhow!("Couldn't read proof from {:?}: {e}", proof_file))?,
+        (None, None) => unreachable!("clap requires --proof or --proof-file"),
+    };
+    let supplied = args
+        .offset
+        .zip(args.size)
+        .map(|(offset, size)| ChunkRange { offset, size });
+
+    let check = proof_check::check_proof(&args.file, target_block_hash, proof, supplied)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&check)?);
+    } else {
+        print_proof_check(&check);
+    }
+    if check.verdict != validate::WindowVerdict::Valid {
+        return Err(anyhow::anyhow!(
+            "Proof isn't valid for the expected chunk: {:?}",
+            check.verdict
+        ));
+    }
+    Ok(())
+}
 
-        // create a proof using the same file we used to create the deal
-        let (_hash, proof) = eth_client
-            .create_proof_helper(target_block, &mut file, deal.file_size.as_u64(), true)
-            .await
-            .expect("Failed to create proof");
+fn print_proof_check(check: &ProofCheck) {
+    println!("file size:         {}", check.file_size);
+    println!("blake3 checksum:   {}", check.blake3_checksum);
+    println!("target block hash: {:?}", check.target_block_hash);
+    println!("proof length:      {}", check.proof_len);
+    println!(
+        "expected chunk:    offset {}, size {} => {:?}",
+        check.expected.offset, check.expected.size, check.verdict
+    );
+    if let (Some(supplied), Some(verdict)) = (check.supplied, check.supplied_verdict) {
+        let note = if supplied == check.expected {
+            ""
+        } else {
+            " (differs from expected)"
+        };
+        println!(
+            "supplied chunk:    offset {}, size {}{note} => {verdict:?}",
+            supplied.offset, supplied.size
+        );
+    }
+}
 
-        let block_num: BlockNum = eth_client
-            .post_proof(deal_id, proof, target_block, None, None)
-            .await
-            .expect("Failed to post proof");
-
-        // Wait for the second window to start
-        // checking that deal is either finished or cancelled
-        let mut current_block_num_2 = block_num;
-        while !EthClient::deal_over(current_block_num_2, deal.clone()) {
-            current_block_num_2 = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
+#[rocket::main]
+async fn main() -> Result<()> {
+    // .env only fills in environment variables, which the command line reads as defaults
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    // JSON lines on stderr, with the fields of every enclosing span; Rocket's own `log` output
+    // is routed through here too. RUST_LOG sets the level, by default info for the server and
+    // warn for the one-off commands, whose output is on stdout
+    let default_level = if cli.command.is_some() {
+        "warn"
+    } else {
+        "info"
+    };
+    tracing_subscriber::fmt()
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level)),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    match &cli.command {
+        Some(Command::VerifyBundle { path }) => return verify_bundle(path),
+        Some(Command::CheckProof(args)) => return check_proof_command(&cli, args).await,
+        _ => {}
+    }
+    let config = Config::load(&cli)?;
+    match &cli.command {
+        Some(Command::Validate { deal_id, json }) => {
+            return validate_command(&config, *deal_id, *json).await
         }
-
-        let response_data: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
-
-        assert_eq!(response_data.data.success_count, 1);
-        assert_eq!(response_data.data.num_windows, 2);
-        Ok(())
+        Some(Command::Inspect { deal_id, json }) => {
+            return inspect_command(&config, *deal_id, *json).await
+        }
+        _ => {}
     }
 
-    #[tokio::test]
-    /// This test verifies that we can submit two correct proofs and get two successes.
-    async fn two_correct_proofs() -> Result<(), anyhow::Error> {
-        let mut file = File::open("test_files/ethereum.pdf").unwrap();
+    let callback = Arc::new(CallbackClient::new(
+        config.bridge_incoming_token.clone(),
+        config.callback_max_attempts,
+        config.callback_initial_backoff(),
+        config.callback_max_backoff(),
+    ));
+    let cache = Arc::new(ResultCache::new(
+        config.result_cache_size,
+        config.confirmation_depth,
+        config.result_cache_path.as_deref(),
+    )?);
+    let settings = config.validation_settings();
+    let jobs = Arc::new(JobStore::open(&config.job_db_path)?);
+
+    let chain = Arc::new(config.connect().await?);
+
+    // forget old jobs now and every hour from here on, so the store doesn't grow forever
+    prune_jobs(&jobs, config.job_retention());
+    spawn({
+        let (jobs, retention) = (jobs.clone(), config.job_retention());
+        async move {
+            loop {
+                sleep(JOB_PRUNE_INTERVAL).await;
+                prune_jobs(&jobs, retention);
+            }
+        }
+    });
 
-        let deal_proposal: DealProposal = DealProposalBuilder::new(
-            "0x0000000000000000000000000000000000000000".to_string(),
-            4,
-            2,
-            0.0,
-            0.0,
-            "0x0000000000000000000000000000000000000000".to_string(),
-        )
-        .with_file(file.try_clone()?)
-        .build()
-        .unwrap();
-        let eth_client = EthClient::default();
+    // pick up async jobs a previous run accepted but didn't finish
+    let unfinished = jobs.unfinished()?;
+    info!("Resuming {} unfinished jobs", unfinished.len());
+    for record in unfinished {
+        spawn(run_job(
+            chain.clone(),
+            cache.clone(),
+            settings.clone(),
+            callback.clone(),
+            jobs.clone(),
+            record,
+        ));
+    }
 
-        let deal_id: DealID = eth_client
-            .propose_deal(deal_proposal.clone(), None, None)
-            .await
-            .expect("Failed to send deal proposal");
+    if config.accepted_bridge_tokens.is_empty() {
+        warn!("No accepted_bridge_tokens configured, anyone who can reach the adapter can use it");
+    }
 
-        let deal = eth_client.get_offer(deal_id).await.unwrap();
+    let figment = rocket::Config::figment()
+        .merge(("address", config.address))
+        .merge(("port", config.port))
+        // escape codes would end up inside the JSON log lines
+        .merge(("cli_colors", false));
+    let state = WebserverState {
+        chain: chain.clone(),
+        probe: chain,
+        cache,
+        settings,
+        callback,
+        jobs,
+        in_flight: InFlight::default(),
+        should_be_async: config.should_be_async,
+        chain_id: config.chain_id,
+        max_block_age: config.max_block_age(),
+    };
+    let _ = adapter(
+        rocket::custom(figment),
+        BridgeTokens::new(&config.accepted_bridge_tokens),
+        state,
+    )
+    .launch()
+    .await?;
 
-        let target_window: usize = eth_client
-            .compute_target_window(deal.deal_start_block, deal.proof_frequency_in_blocks)
-            .await
-            .expect("Failed to compute target window");
+    Ok(())
+}
 
-        let target_block = EthClient::compute_target_block_start(
-            deal.deal_start_block,
-            deal.proof_frequency_in_blocks,
-            target_window,
-        );
+const JOB_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
 
-        dbg!("deal start block: {}", deal.deal_start_block);
-        dbg!("target block start: {}", target_block);
+fn prune_jobs(jobs: &JobStore, retention: Duration) {
+    let updated_before = SystemTime::now()
+        .checked_sub(retention)
+        .unwrap_or(UNIX_EPOCH);
+    match jobs.prune(updated_before) {
+        Ok(pruned) => info!("Forgot {pruned} jobs older than {}s", retention.as_secs()),
+        Err(e) => error!("Couldn't prune the job store: {e}"),
+    }
+}
 
-        let (_hash, proof) = eth_client
-            .create_proof_helper(target_block, &mut file, deal.file_size.as_u64(), true)
-            .await
-            .expect("Failed to create proof");
+/// Mounts the adapter's routes and catchers on `rocket`.
+fn adapter(rocket: Rocket<Build>, tokens: BridgeTokens, state: WebserverState) -> Rocket<Build> {
+    rocket
+        .mount(
+            "/",
+            rocket::routes![
+                compute,
+                report,
+                evidence_bundle,
+                job_status,
+                health_check,
+                ready,
+                version,
+                metrics
+            ],
+        )
+        .register(
+            "/",
+            rocket::catchers![default_catcher, unauthorized_catcher],
+        )
+        .manage(tokens)
+        .manage(state)
+}
 
-        let block_num: BlockNum = eth_client
-            .post_proof(deal_id, proof, target_block, None, None)
-            .await
-            .expect("Failed to post proof");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use banyan_shared::types::{BlockNum, DealID};
+    use chainlink_proof_validator::chain::{DealInfo, MockChain};
+    use rocket::http::Header;
+    use rocket::local::asynchronous::Client;
+
+    #[get("/guarded")]
+    fn guarded(_auth: BridgeAuth) -> &'static str {
+        "ok"
+    }
 
-        let target_block_2 = target_block + deal.proof_frequency_in_blocks;
+    async fn client(tokens: &[&str]) -> Client {
+        let tokens: Vec<String> = tokens.iter().map(|token| token.to_string()).collect();
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![guarded])
+            .register("/", rocket::catchers![unauthorized_catcher])
+            .manage(BridgeTokens::new(&tokens));
+        Client::tracked(rocket).await.expect("valid rocket")
+    }
 
-        let mut current_block_num = block_num;
-        while current_block_num != target_block_2 {
-            current_block_num = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
+    #[rocket::async_test]
+    /// Any of the configured tokens is accepted, and anything else gets a 401 envelope.
+    async fn bridge_tokens_are_checked() {
+        let client = client(&["old-token", "new-token"]).await;
+        for token in ["old-token", "new-token"] {
+            let response = client
+                .get("/guarded")
+                .header(Header::new("Authorization", format!("Bearer {token}")))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
         }
 
-        let (_hash, bad_proof) = eth_client
-            .create_proof_helper(target_block_2, &mut file, deal.file_size.as_u64(), true)
-            .await
-            .expect("Failed to create proof");
-
-        let block_num_2: BlockNum = eth_client
-            .post_proof(deal_id, bad_proof, target_block_2, None, None)
-            .await
-            .expect("Failed to post proof");
-
-        current_block_num = block_num_2;
-        while !EthClient::deal_over(current_block_num, deal.clone()) {
-            current_block_num = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
+        for authorization in [None, Some("Bearer wrong-token"), Some("new-token")] {
+            let mut request = client.get("/guarded");
+            if let Some(authorization) = authorization {
+                request = request.header(Header::new("Authorization", authorization));
+            }
+            let response = request.dispatch().await;
+            assert_eq!(response.status(), Status::Unauthorized);
+            let body: serde_json::Value =
+                serde_json::from_str(&response.into_string().await.expect("body"))
+                    .expect("json body");
+            assert_eq!(body["status"], "errored");
+            assert_eq!(body["error"]["name"], "Unauthorized");
+            assert_eq!(body["statusCode"], 401);
         }
 
-        let response_data: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
-
-        assert_eq!(response_data.data.success_count, 2);
-        assert_eq!(response_data.data.num_windows, 2);
-        Ok(())
+        let open = client(&[]).await;
+        let response = open.get("/guarded").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
     }
 
-    #[tokio::test]
-    /// This test verifies that we can get a success count of 1 when submitting one correct proof and one incorrect proof
-    async fn one_proof_correct_one_incorrect() -> Result<(), anyhow::Error> {
-        let mut file = File::open("test_files/ethereum.pdf").unwrap();
+    /// Readiness reads for an adapter on a `MockChain`, which has no RPC endpoint behind it.
+    struct NoProbe;
 
-        let deal_proposal: DealProposal = DealProposalBuilder::new(
-            "0x0000000000000000000000000000000000000000".to_string(),
-            4,
-            2,
-            0.0,
-            0.0,
-            "0x0000000000000000000000000000000000000000".to_string(),
-        )
-        .with_file(file.try_clone()?)
-        .build()
-        .unwrap();
-        let eth_client = EthClient::default();
+    #[async_trait::async_trait]
+    impl ReadinessProbe for NoProbe {
+        async fn chain_id(&self) -> Result<u64> {
+            Err(anyhow::anyhow!("no RPC endpoint"))
+        }
 
-        let deal_id: DealID = eth_client
-            .propose_deal(deal_proposal.clone(), None, None)
-            .await
-            .expect("Failed to send deal proposal");
+        fn contract_address(&self) -> ethers::types::Address {
+            ethers::types::Address::zero()
+        }
 
-        let deal = eth_client.get_offer(deal_id).await.unwrap();
+        async fn contract_has_code(&self) -> Result<bool> {
+            Err(anyhow::anyhow!("no RPC endpoint"))
+        }
 
-        let target_window: usize = eth_client
-            .compute_target_window(deal.deal_start_block, deal.proof_frequency_in_blocks)
-            .await
-            .expect("Failed to compute target window");
+        async fn latest_block(&self) -> Result<(BlockNum, u64)> {
+            Err(anyhow::anyhow!("no RPC endpoint"))
+        }
+    }
 
-        let target_block = EthClient::compute_target_block_start(
-            deal.deal_start_block,
-            deal.proof_frequency_in_blocks,
-            target_window,
-        );
+    fn deal(deal_start_block: u64, deal_length_in_blocks: u64, proof_frequency: u64) -> DealInfo {
+        DealInfo {
+            deal_start_block: BlockNum(deal_start_block),
+            deal_length_in_blocks: BlockNum(deal_length_in_blocks),
+            proof_frequency_in_blocks: BlockNum(proof_frequency),
+            file_size: 1024,
+            blake3_checksum: blake3::hash(b"banyan"),
+        }
+    }
 
-        dbg!("deal start block: {}", deal.deal_start_block);
-        dbg!("target block start: {}", target_block);
+    fn state(chain: MockChain, should_be_async: bool) -> WebserverState {
+        WebserverState {
+            chain: Arc::new(chain),
+            probe: Arc::new(NoProbe),
+            cache: Arc::new(ResultCache::new(8, 12, None).expect("cache")),
+            settings: validate::ValidationSettings::default(),
+            callback: Arc::new(CallbackClient::new(None, 1, Duration::ZERO, Duration::ZERO)),
+            jobs: Arc::new(JobStore::temporary().expect("job store")),
+            in_flight: InFlight::default(),
+            should_be_async,
+            chain_id: None,
+            max_block_age: Duration::from_secs(60),
+        }
+    }
 
-        let (_hash, proof) = eth_client
-            .create_proof_helper(target_block, &mut file, deal.file_size.as_u64(), true)
+    async fn adapter_client(state: WebserverState) -> Client {
+        Client::tracked(adapter(rocket::build(), BridgeTokens::new(&[]), state))
             .await
-            .expect("Failed to create proof");
+            .expect("valid rocket")
+    }
 
-        let block_num: BlockNum = eth_client
-            .post_proof(deal_id, proof, target_block, None, None)
-            .await
-            .expect("Failed to post proof");
+    async fn post_compute(client: &Client, id: &str, deal_id: &str) -> (Status, serde_json::Value) {
+        let response = client
+            .post("/compute")
+            .header(ContentType::JSON)
+            .body(serde_json::json!({ "id": id, "data": { "deal_id": deal_id } }).to_string())
+            .dispatch()
+            .await;
+        let status = response.status();
+        let body =
+            serde_json::from_str(&response.into_string().await.expect("body")).expect("json body");
+        (status, body)
+    }
 
-        let target_block_2 = target_block + deal.proof_frequency_in_blocks;
+    #[rocket::async_test]
+    /// A score and a final answer about the deal are both 200s for the node to write on-chain,
+    /// told apart by `data.status`.
+    async fn final_answers_are_200s() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        let client = adapter_client(state(chain, false)).await;
+
+        let (status, body) = post_compute(&client, "17", "1").await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body["jobRunID"], "17");
+        assert_eq!(body["statusCode"], 200);
+        assert_eq!(body["data"]["status"], 1);
+        assert_eq!(body["data"]["num_windows"], 2);
+
+        let (status, body) = post_compute(&client, "18", "7").await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body["jobRunID"], "18");
+        assert_eq!(body["statusCode"], 200);
+        assert_eq!(body["data"]["status"], 3);
+    }
 
-        let mut current_block_num = block_num;
-        while current_block_num != target_block_2 {
-            current_block_num = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
+    #[rocket::async_test]
+    /// Anything that isn't an answer gets an errored envelope with its HTTP status, so the node
+    /// retries it or reports it instead of writing it on-chain.
+    async fn failures_are_errored_envelopes() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        // the pinned block, 12 below the latest
+        chain.missing_blocks.insert(118);
+        let client = adapter_client(state(chain, false)).await;
+
+        for (id, deal_id, status, name, code) in [
+            ("19", "1", Status::BadGateway, "BlockHashUnavailable", 7),
+            ("20", "not-a-deal", Status::BadRequest, "InvalidDealId", 2),
+        ] {
+            let (actual_status, body) = post_compute(&client, id, deal_id).await;
+            assert_eq!(actual_status, status, "{name}");
+            assert_eq!(body["jobRunID"], id);
+            assert_eq!(body["status"], "errored");
+            assert_eq!(body["statusCode"], status.code);
+            assert_eq!(body["error"]["name"], name);
+            assert_eq!(body["error"]["code"], code);
         }
-        let (_hash, bad_proof) = eth_client
-            .create_proof_helper(target_block_2, &mut file, deal.file_size.as_u64(), false)
-            .await
-            .expect("Failed to create proof");
 
-        let block_num_2: BlockNum = eth_client
-            .post_proof(deal_id, bad_proof, target_block_2, None, None)
-            .await
-            .expect("Failed to post proof");
-
-        current_block_num = block_num_2;
-        while !EthClient::deal_over(current_block_num, deal.clone()) {
-            current_block_num = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
-        }
+        let mut down = MockChain::new(130);
+        down.fail("get_latest_block_num");
+        let client = adapter_client(state(down, false)).await;
+        let (status, body) = post_compute(&client, "21", "1").await;
+        assert_eq!(status, Status::ServiceUnavailable);
+        assert_eq!(body["jobRunID"], "21");
+        assert_eq!(body["statusCode"], 503);
+        assert_eq!(body["error"]["name"], "RpcUnavailable");
+        assert_eq!(body["error"]["code"], 6);
+    }
 
-        let response_data: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
-
-        assert_eq!(response_data.data.success_count, 1);
-        assert_eq!(response_data.data.num_windows, 2);
-        Ok(())
-    }
-
-    #[tokio::test]
-    /// This test verifies that an empty proof will not be counted as a success.
-    async fn empty_proof_unsuccessful() -> Result<(), anyhow::Error> {
-        let file = File::open("test_files/ethereum.pdf").unwrap();
-        let deal_proposal: DealProposal = DealProposalBuilder::new(
-            "0x0000000000000000000000000000000000000000".to_string(),
-            1,
-            1,
-            0.0,
-            0.0,
-            "0x0000000000000000000000000000000000000000".to_string(),
-        )
-        .with_file(file)
-        .build()
+    #[rocket::async_test]
+    /// A retried request id is answered from the job store, not the chain, and the answer is
+    /// stored as finished so a restart doesn't try to deliver it.
+    async fn retries_are_replayed_from_the_store() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        let state = state(chain, false);
+        let jobs = state.jobs.clone();
+        let client = adapter_client(state).await;
+
+        let (status, first) = post_compute(&client, "17", "1").await;
+        assert_eq!(status, Status::Ok);
+        let record = jobs.get("17").unwrap().expect("stored answer");
+        assert_eq!(record.state, JobState::Answered);
+        assert!(jobs.unfinished().unwrap().is_empty());
+
+        // an answer the chain couldn't give, so it can only have come from the store
+        let stored = serde_json::json!({
+            "jobRunID": "17",
+            "data": { "deal_id": "1", "status": 1, "success_count": 0, "num_windows": 2 },
+            "statusCode": 200
+        });
+        jobs.put(&JobRecord {
+            response: Some(stored.clone()),
+            ..record
+        })
         .unwrap();
-        let eth_client = EthClient::default();
-
-        let deal_id: DealID = eth_client
-            .propose_deal(deal_proposal.clone(), None, None)
-            .await
-            .expect("Failed to send deal proposal");
-
-        let deal = eth_client.get_offer(deal_id).await.unwrap();
-
-        // create a proof using the same file we used to create the deal
-        let proof = Bytes::from(Vec::new());
-
-        let block_num: BlockNum = eth_client
-            .post_proof(deal_id, proof, deal.deal_start_block, None, None)
-            .await
-            .expect("Failed to post proof");
-
-        let mut current_block_num = block_num;
-        while !EthClient::deal_over(current_block_num, deal.clone()) {
-            current_block_num = eth_client
-                .get_latest_block_num()
-                .await
-                .expect("Failed to get current block");
-        }
+        let (status, replayed) = post_compute(&client, "17", "1").await;
+        assert_eq!(status, Status::Ok);
+        assert_ne!(replayed, first);
+        assert_eq!(replayed, stored);
+    }
 
-        let response_data: validate::ChainlinkResponse =
-            rust_chainlink_ea_api_call(deal_id, "http://127.0.0.1:8000/compute".to_string())
-                .await?;
+    #[rocket::async_test]
+    /// A retry of an id that's still being validated waits for that validation's answer.
+    async fn retries_wait_for_the_running_validation() {
+        // no deals, so validating again would answer DealNotFound
+        let state = state(MockChain::new(130), false);
+        let sender = match state.in_flight.claim("17:1") {
+            Claim::New(sender) => sender,
+            Claim::Existing(_) => panic!("nothing is running yet"),
+        };
+        let client = adapter_client(state).await;
+
+        let answer = serde_json::json!({
+            "jobRunID": "17",
+            "data": { "deal_id": "1", "status": 1, "success_count": 2, "num_windows": 2 },
+            "statusCode": 200
+        });
+        let ((status, body), ()) = rocket::tokio::join!(post_compute(&client, "17", "1"), async {
+            rocket::tokio::time::sleep(Duration::from_millis(20)).await;
+            sender
+                .send(Some(answer.clone()))
+                .expect("the retry is waiting");
+        });
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body, answer);
+    }
 
-        assert_eq!(response_data.data.success_count, 0);
-        Ok(())
+    #[rocket::async_test]
+    /// A known request id with a different deal is a new request that reused the id, not a
+    /// retry, so it's refused rather than given another deal's answer.
+    async fn reused_request_ids_are_conflicts() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        chain.add_deal(DealID(2), deal(100, 6, 3));
+        let client = adapter_client(state(chain, false)).await;
+
+        let (status, _) = post_compute(&client, "17", "1").await;
+        assert_eq!(status, Status::Ok);
+        let (status, body) = post_compute(&client, "17", "2").await;
+        assert_eq!(status, Status::Conflict);
+        assert_eq!(body["jobRunID"], "17");
+        assert_eq!(body["statusCode"], 409);
+        assert_eq!(body["error"]["name"], "RequestIdReused");
     }
 }
-*/
@@ -0,0 +1,169 @@
+use crate::error::ValidationError;
+use crate::validate::{ChainlinkResponse, WindowReport, WindowVerdict};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Process-wide metrics, served by the adapter's `/metrics`. All names start with
+/// `proof_validator_`.
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Finished validations, by `outcome`: `success`, or the `ValidationError` name.
+    pub validations: IntCounterVec,
+    /// End-to-end time of a validation, cached answers included.
+    pub validation_duration: Histogram,
+    /// Time of each chain read, by `DealChain` `method`, failures included.
+    pub rpc_duration: HistogramVec,
+    pub rpc_errors: IntCounterVec,
+    pub windows_processed: IntCounter,
+    /// Judged windows, by `verdict` as it appears in `/report`.
+    pub proofs: IntCounterVec,
+    /// Async jobs accepted whose result hasn't been delivered or given up on yet.
+    pub async_jobs_pending: IntGauge,
+    /// Async results that couldn't be delivered after every retry.
+    pub callback_failures: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("proof_validator".to_string()), None)
+            .expect("valid metric prefix");
+        let metrics = Self {
+            validations: IntCounterVec::new(
+                Opts::new("validations_total", "Finished validations by outcome"),
+                &["outcome"],
+            )
+            .expect("valid metric"),
+            validation_duration: Histogram::with_opts(HistogramOpts::new(
+                "validation_duration_seconds",
+                "End-to-end validation latency",
+            ))
+            .expect("valid metric"),
+            rpc_duration: HistogramVec::new(
+                HistogramOpts::new("rpc_duration_seconds", "Chain read latency by method"),
+                &["method"],
+            )
+            .expect("valid metric"),
+            rpc_errors: IntCounterVec::new(
+                Opts::new("rpc_errors_total", "Failed chain reads by method"),
+                &["method"],
+            )
+            .expect("valid metric"),
+            windows_processed: IntCounter::new("windows_processed_total", "Proof windows checked")
+                .expect("valid metric"),
+            proofs: IntCounterVec::new(
+                Opts::new("proofs_total", "Proof windows by verdict"),
+                &["verdict"],
+            )
+            .expect("valid metric"),
+            async_jobs_pending: IntGauge::new("async_jobs_pending", "Async jobs not yet delivered")
+                .expect("valid metric"),
+            callback_failures: IntCounter::new(
+                "callback_failures_total",
+                "Async results that couldn't be delivered",
+            )
+            .expect("valid metric"),
+            registry,
+        };
+        let collectors: [Box<dyn prometheus::core::Collector>; 8] = [
+            Box::new(metrics.validations.clone()),
+            Box::new(metrics.validation_duration.clone()),
+            Box::new(metrics.rpc_duration.clone()),
+            Box::new(metrics.rpc_errors.clone()),
+            Box::new(metrics.windows_processed.clone()),
+            Box::new(metrics.proofs.clone()),
+            Box::new(metrics.async_jobs_pending.clone()),
+            Box::new(metrics.callback_failures.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("metric registered once");
+        }
+        metrics
+    }
+
+    /// Every metric in the Prometheus text format.
+    pub fn gather(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding can't fail");
+        String::from_utf8(buffer).expect("text encoding is utf-8")
+    }
+
+    pub fn record_validation(
+        &self,
+        result: &Result<ChainlinkResponse, ValidationError>,
+        elapsed: Duration,
+    ) {
+        let outcome = match result {
+            Ok(_) => "success",
+            Err(e) => e.name(),
+        };
+        self.validations.with_label_values(&[outcome]).inc();
+        self.validation_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_windows(&self, reports: &[WindowReport]) {
+        self.windows_processed.inc_by(reports.len() as u64);
+        for report in reports {
+            let verdict = match report.verdict {
+                WindowVerdict::Missing => "missing",
+                WindowVerdict::EmptyOrShort => "empty_or_short",
+                WindowVerdict::Malformed => "malformed",
+                WindowVerdict::InvalidMerklePath => "invalid_merkle_path",
+                WindowVerdict::Valid => "valid",
+            };
+            self.proofs.with_label_values(&[verdict]).inc();
+        }
+    }
+
+    /// Runs a chain read, recording how long it took and whether it failed under `method`.
+    pub async fn time_rpc<T>(
+        &self,
+        method: &'static str,
+        call: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let start = Instant::now();
+        let result = call.await;
+        self.rpc_duration
+            .with_label_values(&[method])
+            .observe(start.elapsed().as_secs_f64());
+        if result.is_err() {
+            self.rpc_errors.with_label_values(&[method]).inc();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[tokio::test]
+    /// A failed read shows up under its method in both the latency and the error metrics.
+    async fn rpc_calls_are_recorded() {
+        let errors = METRICS.rpc_errors.with_label_values(&["test_method"]).get();
+        let result: anyhow::Result<()> = METRICS
+            .time_rpc("test_method", async { Err(anyhow!("unreachable")) })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(
+            METRICS.rpc_errors.with_label_values(&["test_method"]).get(),
+            errors + 1
+        );
+
+        let text = METRICS.gather();
+        assert!(text.contains("proof_validator_rpc_duration_seconds_count{method=\"test_method\"}"));
+        assert!(text.contains("proof_validator_rpc_errors_total{method=\"test_method\"}"));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::validate::{judge_proof, WindowVerdict};
+use anyhow::{anyhow, Result};
+use banyan_shared::proofs;
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+/// A byte range of the file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRange {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// The outcome of checking a proof against the file it's meant to be for, without the chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProofCheck {
+    pub file_size: u64,
+    /// blake3 root of the file, the checksum the deal would record, in hex.
+    pub blake3_checksum: String,
+    pub target_block_hash: H256,
+    /// The chunk the target block hash chooses, which validation checks the proof against.
+    pub expected: ChunkRange,
+    /// The chunk the prover says the proof is for, if given.
+    pub supplied: Option<ChunkRange>,
+    pub proof_len: usize,
+    /// Verdict against `expected`, as validation would give it.
+    pub verdict: WindowVerdict,
+    /// Verdict against `supplied`, to tell a wrong chunk from a broken proof.
+    pub supplied_verdict: Option<WindowVerdict>,
+}
+
+/// Hashes the file at `path` and checks `proof` against the chunk `target_block_hash` chooses,
+/// and against `supplied` if given.
+pub fn check_proof(
+    path: &Path,
+    target_block_hash: H256,
+    proof: Vec<u8>,
+    supplied: Option<ChunkRange>,
+) -> Result<ProofCheck> {
+    let mut file = File::open(path).map_err(|e| anyhow!("Couldn't open {:?}: {e}", path))?;
+    let file_size = file.metadata()?.len();
+    // the root of the bao outboard tree is the file's plain blake3 hash
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| anyhow!("Couldn't read {:?}: {e}", path))?;
+    let blake3_checksum = hasher.finalize();
+
+    let (offset, size) =
+        proofs::compute_random_block_choice_from_hash(target_block_hash, file_size);
+    let expected = ChunkRange { offset, size };
+    let supplied_verdict = supplied.map(|supplied| {
+        judge_proof(
+            proof.clone(),
+            blake3_checksum,
+            supplied.offset,
+            supplied.size,
+        )
+    });
+    Ok(ProofCheck {
+        file_size,
+        blake3_checksum: blake3_checksum.to_hex().to_string(),
+        target_block_hash,
+        expected,
+        supplied,
+        proof_len: proof.len(),
+        verdict: judge_proof(proof, blake3_checksum, offset, size),
+        supplied_verdict,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read, Write};
+    use tempfile::NamedTempFile;
+
+    const TARGET_BLOCK_HASH: u64 = 42;
+
+    /// A file that's cleaned up however the test ends, and its contents.
+    fn test_file(len: usize) -> Result<(NamedTempFile, Vec<u8>)> {
+        let contents: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let mut file = NamedTempFile::new()?;
+        file.write_all(&contents)?;
+        file.flush()?;
+        Ok((file, contents))
+    }
+
+    /// A bao slice proving the chunk at `range` of `contents`, as a prover would build it.
+    fn prove(contents: &[u8], range: ChunkRange) -> Vec<u8> {
+        let (encoded, _) = bao::encode::encode(contents);
+        let mut proof = Vec::new();
+        bao::encode::SliceExtractor::new(Cursor::new(&encoded[..]), range.offset, range.size)
+            .read_to_end(&mut proof)
+            .expect("slice of an in-memory encoding");
+        proof
+    }
+
+    fn expected_chunk(file_size: u64) -> ChunkRange {
+        let (offset, size) = proofs::compute_random_block_choice_from_hash(
+            H256::from_low_u64_be(TARGET_BLOCK_HASH),
+            file_size,
+        );
+        ChunkRange { offset, size }
+    }
+
+    #[test]
+    /// The checksum is the file's blake3 hash, and the chunk range falls inside the file.
+    fn empty_proof_is_reported_with_expected_chunk() -> Result<()> {
+        let (file, contents) = test_file(4096)?;
+
+        let check = check_proof(
+            file.path(),
+            H256::from_low_u64_be(TARGET_BLOCK_HASH),
+            Vec::new(),
+            Some(ChunkRange {
+                offset: 0,
+                size: 1024,
+            }),
+        )?;
+        assert_eq!(check.file_size, 4096);
+        assert_eq!(
+            check.blake3_checksum,
+            blake3::hash(&contents).to_hex().to_string()
+        );
+        assert!(check.expected.offset + check.expected.size <= check.file_size);
+        assert_eq!(check.verdict, WindowVerdict::EmptyOrShort);
+        assert_eq!(check.supplied_verdict, Some(WindowVerdict::EmptyOrShort));
+        Ok(())
+    }
+
+    #[test]
+    /// A proof of the chunk the target block hash chooses is valid.
+    fn proof_of_expected_chunk_is_valid() -> Result<()> {
+        let (file, contents) = test_file(16 * 1024)?;
+        let expected = expected_chunk(contents.len() as u64);
+
+        let check = check_proof(
+            file.path(),
+            H256::from_low_u64_be(TARGET_BLOCK_HASH),
+            prove(&contents, expected),
+            None,
+        )?;
+        assert_eq!(check.expected, expected);
+        assert_eq!(check.verdict, WindowVerdict::Valid);
+        assert_eq!(check.supplied_verdict, None);
+        Ok(())
+    }
+
+    #[test]
+    /// A sound proof of the wrong chunk fails against the expected chunk but passes against
+    /// the chunk it was built for.
+    fn proof_of_wrong_chunk_is_told_apart() -> Result<()> {
+        let (file, contents) = test_file(16 * 1024)?;
+        let expected = expected_chunk(contents.len() as u64);
+        let supplied = ChunkRange {
+            offset: if expected.offset == 0 {
+                expected.size
+            } else {
+                0
+            },
+            size: expected.size,
+        };
+
+        let check = check_proof(
+            file.path(),
+            H256::from_low_u64_be(TARGET_BLOCK_HASH),
+            prove(&contents, supplied),
+            Some(supplied),
+        )?;
+        assert_eq!(check.verdict, WindowVerdict::InvalidMerklePath);
+        assert_eq!(check.supplied_verdict, Some(WindowVerdict::Valid));
+        Ok(())
+    }
+}
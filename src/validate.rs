@@ -1,14 +1,56 @@
-use anyhow::{anyhow, Result};
+use crate::blocks::BlockHashError;
+use crate::cache::{CacheKey, ResultCache};
+use crate::chain::{DealChain, DealInfo, InvalidDeal};
+use crate::error::{ValidationError, STATUS_OK};
+use crate::evidence::{DealEvidence, EvidenceBundle, WindowEvidence};
+#[cfg(feature = "metrics")]
+use crate::metrics::METRICS;
 use banyan_shared::{eth::EthClient, proofs, proofs::window, types::*};
-use log::info;
-use rocket::serde::{Deserialize, Serialize};
+use ethers::types::H256;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json::from_str;
+use std::collections::HashMap;
 use std::io::Cursor;
-use std::sync::Arc;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+use tracing::{info, instrument, warn};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ChainlinkRequestData {
     pub deal_id: String,
+    /// Ask for the per-window breakdown in `ResponseData::windows`.
+    #[serde(default)]
+    pub include_windows: bool,
+}
+
+/// Why a single proof window did or didn't count towards `success_count`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowVerdict {
+    /// No proof was recorded for the window.
+    Missing,
+    /// A proof was recorded but its log was empty or too short to hold one.
+    EmptyOrShort,
+    /// The proof bytes couldn't be read as a merkle proof.
+    Malformed,
+    /// The proof was readable but doesn't prove the chunk chosen by the target block hash.
+    InvalidMerklePath,
+    Valid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WindowReport {
+    pub window_num: usize,
+    pub target_block: BlockNum,
+    pub target_block_hash: H256,
+    /// Block the proof for this window landed in, if one was submitted.
+    pub proof_block: Option<BlockNum>,
+    /// Length of the logged proof, 0 for an empty one. None when no proof was submitted.
+    pub proof_len: Option<usize>,
+    pub chunk_offset: u64,
+    pub chunk_size: u64,
+    pub verdict: WindowVerdict,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -18,159 +60,1059 @@ pub struct ResponseData {
     pub num_windows: u64,
     pub status: u16,
     pub result: String,
+    /// Block the verdict is pinned to. All contract state is read as of this block, and
+    /// everything the verdict depends on is at or below it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_block: Option<BlockNum>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_block_hash: Option<H256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows: Option<Vec<WindowReport>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChainlinkResponse {
     pub data: ResponseData,
 }
-pub struct WebserverState(pub Arc<EthClient>);
+/// Which block a verdict is pinned to, so that a reorg can't change it afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finality {
+    /// This many blocks below the latest block.
+    ConfirmationDepth(u64),
+    /// The node's `finalized` block.
+    FinalizedTag,
+}
 
-/* Function to construct an error response to return to Chainlink */
-fn construct_error(deal_id: DealID, reason: String) -> ChainlinkResponse {
-    ChainlinkResponse {
-        data: ResponseData {
+/// Knobs for a validation run that don't come from the request.
+#[derive(Debug, Clone)]
+pub struct ValidationSettings {
+    /// How many proof windows are fetched from the chain at once.
+    pub window_concurrency: usize,
+    pub finality: Finality,
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        Self {
+            window_concurrency: 8,
+            finality: Finality::ConfirmationDepth(12),
+        }
+    }
+}
+
+impl ResponseData {
+    /// The body written on-chain for a final answer that isn't a score, so the contract can
+    /// branch on `status`.
+    pub fn from_error(deal_id: DealID, error: &ValidationError) -> Self {
+        ResponseData {
             deal_id,
             success_count: 0,
             num_windows: 0,
-            status: 0,
-            result: reason,
+            status: error.status(),
+            result: error.to_string(),
+            pinned_block: None,
+            pinned_block_hash: None,
+            windows: None,
+        }
+    }
+}
+
+/// First block of the window, whose hash chooses the chunk to prove.
+pub(crate) fn target_block(deal_info: &DealInfo, window_num: usize) -> BlockNum {
+    EthClient::compute_target_block_start(
+        deal_info.deal_start_block,
+        deal_info.proof_frequency_in_blocks,
+        window_num,
+    )
+}
+
+/// Number of windows that count for a deal, given when (if ever) it was cancelled. A deal
+/// cancelled before its first window ended has none, and is scored 0 out of 0.
+pub(crate) fn count_windows(
+    deal_id: DealID,
+    deal_info: &DealInfo,
+    cancellation_block: Option<BlockNum>,
+) -> Result<usize, ValidationError> {
+    // only the windows before the cancellation took effect are counted
+    let deal_length_in_blocks = match cancellation_block {
+        Some(agreed_upon_cancellation_block) => {
+            agreed_upon_cancellation_block - deal_info.deal_start_block
+        }
+        None => deal_info.deal_length_in_blocks,
+    };
+    let num_windows =
+        window::get_num_windows(deal_length_in_blocks, deal_info.proof_frequency_in_blocks)
+            .map_err(|e| ValidationError::InvalidDealParameters {
+                deal_id,
+                reason: format!("Could not get number of windows: {e}"),
+            })?;
+    // a deal that ran for less than a window before being cancelled has nothing to prove, but
+    // one that never had a window was set up wrong
+    if num_windows == 0 && cancellation_block.is_none() {
+        return Err(ValidationError::InvalidDealParameters {
+            deal_id,
+            reason: "No windows found".to_string(),
+        });
+    }
+    Ok(num_windows)
+}
+
+/// The report for a window before its proof is looked at, with the chunk chosen by the target
+/// block hash.
+pub(crate) fn new_window_report(
+    deal_info: &DealInfo,
+    window_num: usize,
+    target_block_hash: H256,
+) -> WindowReport {
+    let (chunk_offset, chunk_size) =
+        proofs::compute_random_block_choice_from_hash(target_block_hash, deal_info.file_size);
+    WindowReport {
+        window_num,
+        target_block: target_block(deal_info, window_num),
+        target_block_hash,
+        proof_block: None,
+        proof_len: None,
+        chunk_offset,
+        chunk_size,
+        verdict: WindowVerdict::Missing,
+    }
+}
+
+/// Sets the verdict of `report` from the proof logged in its `proof_block`. Needs no chain
+/// access, so evidence bundles are judged by exactly the same code.
+pub(crate) fn judge_window(
+    report: &mut WindowReport,
+    proof: Option<Vec<u8>>,
+    blake3_checksum: blake3::Hash,
+) {
+    if report.proof_block.is_none() {
+        info!(window_num = report.window_num, "No proof submitted");
+        report.verdict = WindowVerdict::Missing;
+        return;
+    }
+    let proof_bytes = proof.unwrap_or_default();
+    report.proof_len = Some(proof_bytes.len());
+    report.verdict = judge_proof(
+        proof_bytes,
+        blake3_checksum,
+        report.chunk_offset,
+        report.chunk_size,
+    );
+    match report.verdict {
+        WindowVerdict::EmptyOrShort => {
+            info!(window_num = report.window_num, "Proof is too short")
+        }
+        WindowVerdict::Valid => info!(window_num = report.window_num, "Proof succeeded"),
+        _ => info!(window_num = report.window_num, "Proof failed"),
+    }
+}
+
+/// Checks a proof of the chunk at `chunk_offset` against the file's blake3 checksum. Shared by
+/// validation and the local proof check, so both accept exactly the same proofs.
+pub fn judge_proof(
+    proof: Vec<u8>,
+    blake3_checksum: blake3::Hash,
+    chunk_offset: u64,
+    chunk_size: u64,
+) -> WindowVerdict {
+    if proof.is_empty() {
+        return WindowVerdict::EmptyOrShort;
+    }
+    // TODO is there an issue of coercing the Vec<u8> into a &[u8] here?
+    match EthClient::check_if_merkle_proof_is_valid(
+        Cursor::new(&proof),
+        blake3_checksum,
+        chunk_offset,
+        chunk_size,
+    ) {
+        Ok(true) => WindowVerdict::Valid,
+        Ok(false) => WindowVerdict::InvalidMerklePath,
+        Err(e) => {
+            info!("Error reading proof: {e}");
+            WindowVerdict::Malformed
+        }
+    }
+}
+
+/// The scored answer for a deal from the reports of all its windows.
+pub(crate) fn build_response(
+    deal_id: DealID,
+    pinned_block: BlockNum,
+    pinned_block_hash: H256,
+    window_reports: Vec<WindowReport>,
+) -> ChainlinkResponse {
+    let success_count = window_reports
+        .iter()
+        .filter(|report| report.verdict == WindowVerdict::Valid)
+        .count() as u64;
+    ChainlinkResponse {
+        data: ResponseData {
+            deal_id,
+            success_count,
+            num_windows: window_reports.len() as u64,
+            status: STATUS_OK,
+            result: "Ok".to_string(),
+            pinned_block: Some(pinned_block),
+            pinned_block_hash: Some(pinned_block_hash),
+            windows: Some(window_reports),
         },
     }
 }
 
-/// this validates the deal based on a deal_id, returns a json response of either the success count and num_windows,
-/// or an error message to be turned into Json<ChainlinkResponse> in the caller!
-/// TODO fix logging... :|
-pub(crate) async fn validate_deal_internal(
-    provider: Arc<EthClient>,
-    input_data: ChainlinkRequestData,
-) -> Result<ChainlinkResponse> {
-    let deal_id = from_str(&input_data.deal_id)?;
-    let deal_info = provider
-        .get_offer(deal_id)
+/// Fetches everything needed to judge one proof window and judges it. Target block hashes come
+/// from `block_hashes`, fetched for all windows at once. Proofs are looked up in `proof_logs`
+/// (block => proof, from one range scan) when given, or read block by block if not. Also
+/// returns the logged proof, for the evidence bundle.
+#[instrument(skip_all, fields(window_num = window_num))]
+async fn check_window<C: DealChain + ?Sized>(
+    chain: &C,
+    deal_id: DealID,
+    deal_info: &DealInfo,
+    pinned_block: BlockNum,
+    block_hashes: &HashMap<u64, H256>,
+    proof_logs: Option<&HashMap<u64, Vec<u8>>>,
+    window_num: usize,
+) -> Result<(WindowReport, Option<Vec<u8>>), ValidationError> {
+    let target_window_start = target_block(deal_info, window_num);
+    let target_block_hash = *block_hashes.get(&target_window_start.0).ok_or_else(|| {
+        ValidationError::BlockHashUnavailable {
+            block_num: target_window_start,
+            reason: "Missing from batch".to_string(),
+        }
+    })?;
+    let mut report = new_window_report(deal_info, window_num, target_block_hash);
+
+    report.proof_block = chain
+        .get_proof_block_num_from_window(deal_id, window_num as u64, pinned_block)
         .await
-        .map_err(|e| anyhow!("Error in get_deal: {:?}", e))?;
+        .map_err(|e| {
+            ValidationError::RpcUnavailable(format!(
+                "Could not get block where proof was submitted for window {window_num}: {e}"
+            ))
+        })?;
 
-    // checking that deal is either finished or cancelled
-    let current_block_num = provider
-        .get_latest_block_num()
+    let logged_proof = match (report.proof_block, proof_logs) {
+        (None, _) => None,
+        (Some(block_num), Some(proof_logs)) => proof_logs.get(&block_num.0).cloned(),
+        (Some(block_num), None) => chain
+            .get_proof_from_logs(block_num, deal_id)
+            .await
+            .map_err(|e| ValidationError::LogDecodeFailure {
+                block_num,
+                reason: e.to_string(),
+            })?,
+    };
+    judge_window(&mut report, logged_proof.clone(), deal_info.blake3_checksum);
+    Ok((report, logged_proof))
+}
+
+/// Blames a failed `get_block_hashes` on the block that failed, or on the RPC provider when
+/// the whole request did.
+pub(crate) fn block_hashes_error(e: anyhow::Error) -> ValidationError {
+    match e.downcast::<BlockHashError>() {
+        Ok(BlockHashError { block_num, reason }) => ValidationError::BlockHashUnavailable {
+            block_num: BlockNum(block_num),
+            reason,
+        },
+        Err(e) => ValidationError::RpcUnavailable(format!("Couldn't get block hashes: {e}")),
+    }
+}
+
+/// Blames a failed `get_offer` on the deal when its parameters are unusable, and on the RPC
+/// provider otherwise.
+fn get_offer_error(deal_id: DealID, e: anyhow::Error) -> ValidationError {
+    match e.downcast::<InvalidDeal>() {
+        Ok(InvalidDeal(reason)) => ValidationError::InvalidDealParameters { deal_id, reason },
+        Err(e) => ValidationError::RpcUnavailable(format!("Error in get_offer: {e}")),
+    }
+}
+
+/// The block the deal ends at. Its last proof can land up to a window later, so that block has
+/// to exist too, or the deal's parameters are invalid.
+pub(crate) fn checked_end_block(
+    deal_id: DealID,
+    deal_info: &DealInfo,
+) -> Result<BlockNum, ValidationError> {
+    deal_info
+        .deal_end_block()
+        .filter(|end| {
+            end.0
+                .checked_add(deal_info.proof_frequency_in_blocks.0)
+                .is_some()
+        })
+        .ok_or_else(|| ValidationError::InvalidDealParameters {
+            deal_id,
+            reason: format!(
+                "Deal from block {} for {} blocks, proven every {}, ends past the last block",
+                deal_info.deal_start_block.0,
+                deal_info.deal_length_in_blocks.0,
+                deal_info.proof_frequency_in_blocks.0
+            ),
+        })
+}
+
+pub(crate) async fn read_latest_block<C: DealChain + ?Sized>(
+    chain: &C,
+) -> Result<BlockNum, ValidationError> {
+    chain.get_latest_block_num().await.map_err(|e| {
+        ValidationError::RpcUnavailable(format!("Couldn't get most recent block number: {e}"))
+    })
+}
+
+pub(crate) async fn read_offer<C: DealChain + ?Sized>(
+    chain: &C,
+    deal_id: DealID,
+    block_num: BlockNum,
+) -> Result<Option<DealInfo>, ValidationError> {
+    chain
+        .get_offer(deal_id, block_num)
+        .await
+        .map_err(|e| get_offer_error(deal_id, e))
+}
+
+/// The block the deal ends at, and the block it was cancelled at as of `block_num`. A
+/// cancellation only matters if it took effect before the deal would have ended anyway.
+pub(crate) async fn read_deal_end<C: DealChain + ?Sized>(
+    chain: &C,
+    deal_id: DealID,
+    deal_info: &DealInfo,
+    block_num: BlockNum,
+) -> Result<(BlockNum, Option<BlockNum>), ValidationError> {
+    let deal_end_block = checked_end_block(deal_id, deal_info)?;
+    let cancellation_block = chain
+        .get_cancellation_block(deal_id, deal_info.deal_start_block, block_num)
+        .await
+        .map_err(|e| {
+            ValidationError::RpcUnavailable(format!("Couldn't get cancellation status: {e}"))
+        })?
+        .filter(|block_num| block_num.0 < deal_end_block.0);
+    Ok((deal_end_block, cancellation_block))
+}
+
+/// A deal and the block its validation is pinned to, read before anything else.
+struct PinnedDeal {
+    deal_id: DealID,
+    deal_info: DealInfo,
+    current_block_num: BlockNum,
+    pinned_block: BlockNum,
+    pinned_block_hash: H256,
+}
+
+async fn pin_deal<C: DealChain + ?Sized>(
+    chain: &C,
+    settings: &ValidationSettings,
+    input_data: &ChainlinkRequestData,
+) -> Result<PinnedDeal, ValidationError> {
+    let deal_id: DealID = from_str(&input_data.deal_id)
+        .map_err(|_| ValidationError::InvalidDealId(input_data.deal_id.clone()))?;
+
+    let current_block_num = read_latest_block(chain).await?;
+
+    // every contract read below is made as of this block, so the verdict can be reproduced
+    let pinned_block = match settings.finality {
+        Finality::ConfirmationDepth(depth) => BlockNum(current_block_num.0.saturating_sub(depth)),
+        Finality::FinalizedTag => chain.get_finalized_block_num().await.map_err(|e| {
+            ValidationError::RpcUnavailable(format!("Couldn't get finalized block number: {e}"))
+        })?,
+    };
+    let pinned_block_hash = chain
+        .get_block_hash_from_num(pinned_block)
         .await
-        .map_err(|e| anyhow!("Couldn't get most recent block number: {e}"))?;
+        .map_err(|e| ValidationError::BlockHashUnavailable {
+            block_num: pinned_block,
+            reason: e.to_string(),
+        })?;
+
+    let deal_info = match read_offer(chain, deal_id, pinned_block).await? {
+        Some(deal_info) => deal_info,
+        // a deal created since the pinned block exists, it just isn't final yet
+        None => match read_offer(chain, deal_id, current_block_num).await? {
+            Some(deal_info) => {
+                return Err(ValidationError::NotFinalized {
+                    deal_id,
+                    final_block: checked_end_block(deal_id, &deal_info)?
+                        + deal_info.proof_frequency_in_blocks,
+                    pinned_block,
+                })
+            }
+            None => return Err(ValidationError::DealNotFound(deal_id)),
+        },
+    };
+
+    Ok(PinnedDeal {
+        deal_id,
+        deal_info,
+        current_block_num,
+        pinned_block,
+        pinned_block_hash,
+    })
+}
+
+/// Scores a pinned deal, keeping everything that went into the score.
+async fn score_deal<C: DealChain + ?Sized>(
+    chain: &C,
+    settings: &ValidationSettings,
+    pinned: &PinnedDeal,
+) -> Result<EvidenceBundle, ValidationError> {
+    let PinnedDeal {
+        deal_id,
+        ref deal_info,
+        current_block_num,
+        pinned_block,
+        pinned_block_hash,
+    } = *pinned;
 
     // TODO: Why have any of these checks in the API. Shouldn't they all be in the Smart Contract Logic.
 
-    let deal_over = EthClient::deal_over(current_block_num, deal_info.clone());
-    let deal_cancelled = false; // TODO need to figure out how to get this
+    // checking that deal is either finished or cancelled
+    let deal_over = deal_info.deal_over(current_block_num);
+
+    let (deal_end_block, cancellation_block) =
+        read_deal_end(chain, deal_id, deal_info, pinned_block).await?;
+    let deal_cancelled = cancellation_block.is_some();
 
     // this refuses to do the validation computations unless the deal is done with or cancelled
     if !deal_over && !deal_cancelled {
-        return Ok(construct_error(deal_id, "Deal is ongoing".to_string()));
+        return Err(ValidationError::DealOngoing {
+            deal_id,
+            deal_end_block,
+        });
+    }
+
+    // a proof lands at most one window after its target, so nothing can change past this block
+    let final_block =
+        cancellation_block.unwrap_or(deal_end_block) + deal_info.proof_frequency_in_blocks;
+    if final_block.0 > pinned_block.0 {
+        return Err(ValidationError::NotFinalized {
+            deal_id,
+            final_block,
+            pinned_block,
+        });
     }
 
-    // this computes the actual deal length based on potential cancellation? and gets real number of windows :)
-    let agreed_upon_cancellation_block: BlockNum = BlockNum(0u64); // need to figure out how to get this
-    let deal_length_in_blocks = if deal_cancelled {
-        agreed_upon_cancellation_block - deal_info.deal_start_block
-    } else {
-        deal_info.deal_length_in_blocks
+    let num_windows = count_windows(deal_id, deal_info, cancellation_block)?;
+
+    let target_blocks: Vec<BlockNum> = (0..num_windows)
+        .map(|window_num| target_block(deal_info, window_num))
+        .collect();
+    let block_hashes = chain
+        .get_block_hashes(&target_blocks, current_block_num)
+        .await
+        .map_err(block_hashes_error)?;
+
+    // one scan up to the final block finds every proof
+    let proof_logs = match chain
+        .get_proofs_in_range(deal_id, deal_info.deal_start_block, final_block)
+        .await
+    {
+        Ok(proof_logs) => Some(proof_logs),
+        Err(e) => {
+            warn!("Couldn't scan proof logs, reading them per window: {e}");
+            None
+        }
     };
 
-    let num_windows =
-        window::get_num_windows(deal_length_in_blocks, deal_info.proof_frequency_in_blocks)
-            .map_err(|e| anyhow!("Could not get number of windows: {e}"))?;
-
-    // iterating over proof blocks (by window)
-    let mut success_count = 0;
-    for window_num in 0..num_windows {
-        let target_window_start = EthClient::compute_target_block_start(
-            deal_info.deal_start_block,
-            deal_info.proof_frequency_in_blocks,
-            window_num,
+    // checking proof windows concurrently, but reporting them in window order
+    let checked_windows: Vec<(WindowReport, Option<Vec<u8>>)> = stream::iter(0..num_windows)
+        .map(|window_num| {
+            check_window(
+                chain,
+                deal_id,
+                deal_info,
+                pinned_block,
+                &block_hashes,
+                proof_logs.as_ref(),
+                window_num,
+            )
+        })
+        .buffered(settings.window_concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    let windows = checked_windows
+        .iter()
+        .map(|(report, proof)| WindowEvidence::new(report, proof.clone()))
+        .collect();
+    let window_reports: Vec<WindowReport> = checked_windows
+        .into_iter()
+        .map(|(report, _)| report)
+        .collect();
+    #[cfg(feature = "metrics")]
+    METRICS.record_windows(&window_reports);
+    Ok(EvidenceBundle {
+        deal: DealEvidence::new(deal_id, deal_info),
+        cancellation_block,
+        pinned_block,
+        pinned_block_hash,
+        windows,
+        response: build_response(deal_id, pinned_block, pinned_block_hash, window_reports),
+    })
+}
+
+/// this validates the deal based on a deal_id, returns a json response of the success count and num_windows,
+/// or the reason the deal couldn't be validated, to be turned into Json in the caller!
+/// Logs under a `validate` span tagged with the requested deal id.
+#[instrument(name = "validate", skip_all, fields(deal_id = %input_data.deal_id))]
+pub async fn validate_deal_internal<C: DealChain + ?Sized>(
+    chain: &C,
+    cache: Option<&ResultCache>,
+    settings: &ValidationSettings,
+    input_data: ChainlinkRequestData,
+) -> Result<ChainlinkResponse, ValidationError> {
+    #[cfg(feature = "metrics")]
+    let start = Instant::now();
+    let result = validate_deal(chain, cache, settings, input_data).await;
+    #[cfg(feature = "metrics")]
+    METRICS.record_validation(&result, start.elapsed());
+    result
+}
+
+async fn validate_deal<C: DealChain + ?Sized>(
+    chain: &C,
+    cache: Option<&ResultCache>,
+    settings: &ValidationSettings,
+    input_data: ChainlinkRequestData,
+) -> Result<ChainlinkResponse, ValidationError> {
+    let pinned = pin_deal(chain, settings, &input_data).await?;
+
+    // finalized deals never change, so skip all the per-window reads if we've seen this one
+    let cache_key = CacheKey::new(pinned.deal_id, &pinned.deal_info);
+    if let Some(mut response) = cache.and_then(|cache| cache.get(&cache_key)) {
+        info!("Using cached result");
+        if !input_data.include_windows {
+            response.data.windows = None;
+        }
+        return Ok(response);
+    }
+
+    let mut response = score_deal(chain, settings, &pinned).await?.response;
+    if let (Some(cache), Some(last_window)) =
+        (cache, response.data.windows.as_ref().and_then(|w| w.last()))
+    {
+        let last_window_end = last_window.target_block + pinned.deal_info.proof_frequency_in_blocks;
+        cache.insert_if_final(
+            cache_key,
+            last_window_end,
+            pinned.current_block_num,
+            &response,
         );
+    }
+    if !input_data.include_windows {
+        response.data.windows = None;
+    }
+    Ok(response)
+}
 
-        let target_block_hash = provider
-            .get_block_hash_from_num(target_window_start)
-            .await
-            .map_err(|e| anyhow!("Could not get block hash: {e}"))?;
+/// Validates the deal like `validate_deal_internal`, bypassing the cache, and returns the
+/// evidence bundle the verdict can be re-checked from offline.
+#[instrument(skip_all, fields(deal_id = %input_data.deal_id))]
+pub async fn collect_evidence<C: DealChain + ?Sized>(
+    chain: &C,
+    settings: &ValidationSettings,
+    input_data: ChainlinkRequestData,
+) -> Result<EvidenceBundle, ValidationError> {
+    let pinned = pin_deal(chain, settings, &input_data).await?;
+    score_deal(chain, settings, &pinned).await
+}
 
-        let submitted_proof_in_block_num = match provider
-            .get_proof_block_num_from_window(deal_id, window_num as u64)
-            .await
-            .map_err(|e| {
-                anyhow!("Could not get block where proof was submitted for this window: {e}")
-            })? {
-            Some(block_num) => block_num,
-            None => {
-                info!("No proof submitted for window {}", window_num);
-                continue;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::{DealInfo, MockChain};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn deal(deal_start_block: u64, deal_length_in_blocks: u64, proof_frequency: u64) -> DealInfo {
+        DealInfo {
+            deal_start_block: BlockNum(deal_start_block),
+            deal_length_in_blocks: BlockNum(deal_length_in_blocks),
+            proof_frequency_in_blocks: BlockNum(proof_frequency),
+            file_size: 1024,
+            blake3_checksum: blake3::hash(b"banyan"),
+        }
+    }
+
+    fn request(deal_id: DealID) -> ChainlinkRequestData {
+        ChainlinkRequestData {
+            deal_id: deal_id.0.to_string(),
+            include_windows: true,
+        }
+    }
+
+    #[tokio::test]
+    /// A deal that hasn't ended and wasn't cancelled is not scored.
+    async fn ongoing_deal_is_not_validated() {
+        let mut chain = MockChain::new(105);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+
+        let error = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await
+        .expect_err("deal is still running");
+        assert_eq!(
+            error,
+            ValidationError::DealOngoing {
+                deal_id: DealID(1),
+                deal_end_block: BlockNum(106),
             }
-        };
+        );
+    }
 
-        let proof_bytes: Vec<u8> = match provider
-            .get_proof_from_logs(submitted_proof_in_block_num, deal_id)
-            .await
-            .map_err(|e| {
-                anyhow!(
-                    "Couldn't get log from block {}: {}",
-                    submitted_proof_in_block_num.0,
-                    e
-                )
-            })? {
-            Some(proof) => proof,
-            None => {
-                info!("Proof is too short for window {}", window_num);
-                continue;
+    #[tokio::test]
+    /// Unknown deals and unparseable deal ids are told apart.
+    async fn unknown_deal_and_bad_deal_id() {
+        let chain = MockChain::new(105);
+
+        let error = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(7)),
+        )
+        .await
+        .expect_err("no such deal");
+        assert_eq!(error, ValidationError::DealNotFound(DealID(7)));
+
+        let mut bad_request = request(DealID(7));
+        bad_request.deal_id = "seven".to_string();
+        let error =
+            validate_deal_internal(&chain, None, &ValidationSettings::default(), bad_request)
+                .await
+                .expect_err("deal id isn't a number");
+        assert_eq!(error.status(), 2);
+        assert_eq!(error.http_status(), 400);
+    }
+
+    /// Wraps a `MockChain`, making each `getProofBlock` read take longer the earlier its window
+    /// is, and recording how many of them were running at once.
+    struct SlowChain {
+        inner: MockChain,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl SlowChain {
+        fn new(inner: MockChain) -> Self {
+            Self {
+                inner,
+                running: AtomicUsize::new(0),
+                max_running: AtomicUsize::new(0),
             }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DealChain for SlowChain {
+        async fn get_offer(
+            &self,
+            deal_id: DealID,
+            block_num: BlockNum,
+        ) -> anyhow::Result<Option<DealInfo>> {
+            self.inner.get_offer(deal_id, block_num).await
+        }
+
+        async fn get_latest_block_num(&self) -> anyhow::Result<BlockNum> {
+            self.inner.get_latest_block_num().await
+        }
+
+        async fn get_finalized_block_num(&self) -> anyhow::Result<BlockNum> {
+            self.inner.get_finalized_block_num().await
+        }
+
+        async fn get_block_hash_from_num(&self, block_num: BlockNum) -> anyhow::Result<H256> {
+            self.inner.get_block_hash_from_num(block_num).await
+        }
+
+        async fn get_proof_block_num_from_window(
+            &self,
+            deal_id: DealID,
+            window_num: u64,
+            block_num: BlockNum,
+        ) -> anyhow::Result<Option<BlockNum>> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(5 * (10 - window_num))).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.inner
+                .get_proof_block_num_from_window(deal_id, window_num, block_num)
+                .await
+        }
+
+        async fn get_proof_from_logs(
+            &self,
+            block_num: BlockNum,
+            deal_id: DealID,
+        ) -> anyhow::Result<Option<Vec<u8>>> {
+            self.inner.get_proof_from_logs(block_num, deal_id).await
+        }
+
+        async fn get_proofs_in_range(
+            &self,
+            deal_id: DealID,
+            from_block: BlockNum,
+            to_block: BlockNum,
+        ) -> anyhow::Result<HashMap<u64, Vec<u8>>> {
+            self.inner
+                .get_proofs_in_range(deal_id, from_block, to_block)
+                .await
+        }
+
+        async fn get_cancellation_block(
+            &self,
+            deal_id: DealID,
+            deal_start_block: BlockNum,
+            latest_block_num: BlockNum,
+        ) -> anyhow::Result<Option<BlockNum>> {
+            self.inner
+                .get_cancellation_block(deal_id, deal_start_block, latest_block_num)
+                .await
+        }
+    }
+
+    #[tokio::test]
+    /// Windows without a proof, or with an empty one, are reported and not counted.
+    async fn missing_and_empty_proofs_are_unsuccessful() -> Result<(), ValidationError> {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        chain.add_proof(DealID(1), 1, BlockNum(104), Vec::new());
+
+        let response = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await?;
+        assert_eq!(response.data.status, 1);
+        assert_eq!(response.data.success_count, 0);
+        assert_eq!(response.data.num_windows, 2);
+        let windows = response.data.windows.expect("windows were requested");
+        let verdicts: Vec<WindowVerdict> = windows.iter().map(|report| report.verdict).collect();
+        assert_eq!(
+            verdicts,
+            vec![WindowVerdict::Missing, WindowVerdict::EmptyOrShort]
+        );
+        // an empty proof was still submitted, unlike a missing one
+        let proof_lens: Vec<Option<usize>> =
+            windows.iter().map(|report| report.proof_len).collect();
+        assert_eq!(proof_lens, vec![None, Some(0)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// Windows are read concurrently, at most `window_concurrency` at a time, and reported in
+    /// window order even though the later reads finish first.
+    async fn windows_are_read_concurrently_and_reported_in_order() -> Result<(), ValidationError> {
+        let mut mock = MockChain::new(200);
+        mock.add_deal(DealID(1), deal(100, 24, 3));
+        for window_num in (0..8).step_by(2) {
+            mock.add_proof(
+                DealID(1),
+                window_num,
+                BlockNum(100 + 3 * window_num + 1),
+                Vec::new(),
+            );
+        }
+        let chain = SlowChain::new(mock);
+        let settings = ValidationSettings {
+            window_concurrency: 3,
+            ..Default::default()
         };
-        let (chunk_offset, chunk_size) = proofs::compute_random_block_choice_from_hash(
-            target_block_hash,
-            deal_info.file_size.as_u64(),
+
+        let response = validate_deal_internal(&chain, None, &settings, request(DealID(1))).await?;
+        let windows = response.data.windows.expect("windows were requested");
+        assert_eq!(
+            windows.iter().map(|w| w.window_num).collect::<Vec<_>>(),
+            (0..8).collect::<Vec<_>>()
         );
+        for report in &windows {
+            let expected = if report.window_num % 2 == 0 {
+                WindowVerdict::EmptyOrShort
+            } else {
+                WindowVerdict::Missing
+            };
+            assert_eq!(report.verdict, expected, "window {}", report.window_num);
+        }
+        assert_eq!(chain.max_running.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// A deal created after the pinned block isn't final yet, rather than not found.
+    async fn new_deal_is_not_finalized() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(125, 6, 3));
+        chain.deal_created.insert(1, BlockNum(125));
 
-        // TODO is there an issue of coercing the Vec<u8> into a &[u8] here?
-        match EthClient::check_if_merkle_proof_is_valid(
-            Cursor::new(&proof_bytes),
-            deal_info.blake3_checksum.hash(),
-            chunk_offset,
-            chunk_size,
+        let error = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
         )
-        .map_err(|e| {
-            anyhow!(
-                "Error reading proof {}: {}",
-                submitted_proof_in_block_num.0,
-                e
-            )
-        })? {
-            true => {
-                info!("Proof succeeded for window {}", window_num);
-                success_count += 1;
+        .await
+        .expect_err("the deal is newer than the pinned block");
+        assert_eq!(
+            error,
+            ValidationError::NotFinalized {
+                deal_id: DealID(1),
+                final_block: BlockNum(134),
+                pinned_block: BlockNum(118),
             }
-            false => {
-                info!("Proof failed for window {}", window_num);
-                continue;
+        );
+    }
+
+    #[tokio::test]
+    /// A failed `getProofBlock` read is an RPC outage, not a bad proof.
+    async fn proof_block_read_failure_is_rpc_unavailable() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        chain.fail("get_proof_block_num_from_window");
+
+        let error = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await
+        .expect_err("proof blocks can't be read");
+        assert!(matches!(error, ValidationError::RpcUnavailable(_)));
+        assert_eq!(error.http_status(), 503);
+    }
+
+    #[tokio::test]
+    /// A block hash that can't be read is blamed on its own block, not the first window's.
+    async fn missing_block_hash_names_the_block() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        let second_target = target_block(&deal(100, 6, 3), 1);
+        chain.missing_blocks.insert(second_target.0);
+
+        let error = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await
+        .expect_err("a target block hash is missing");
+        match error {
+            ValidationError::BlockHashUnavailable { block_num, .. } => {
+                assert_eq!(block_num, second_target)
             }
+            other => panic!("expected BlockHashUnavailable, got {other:?}"),
         }
     }
-    if num_windows > 0 {
-        Ok(ChainlinkResponse {
-            data: ResponseData {
-                deal_id,
-                success_count,
-                num_windows: num_windows as u64,
-                status: 1,
-                result: "Ok".to_string(),
-            },
-        })
-    } else {
-        Ok(ChainlinkResponse {
-            data: ResponseData {
-                deal_id,
-                success_count,
-                num_windows: num_windows as u64,
-                status: 0,
-                result: "No windows found".to_string(),
-            },
-        })
+
+    #[tokio::test]
+    /// The range scan finds a proof that landed after the deal ended, in the last window's grace period.
+    async fn late_proof_is_found_by_range_scan() -> Result<(), ValidationError> {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        chain.add_proof(DealID(1), 1, BlockNum(106), Vec::new());
+        // the per-window reads would find the proof too, so make sure they aren't what found it
+        chain.fail("get_proof_from_logs");
+
+        let response = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await?;
+        let windows = response.data.windows.expect("windows were requested");
+        assert_eq!(windows[1].proof_block, Some(BlockNum(106)));
+        assert_eq!(windows[1].verdict, WindowVerdict::EmptyOrShort);
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// A cancelled deal can be validated before its end, and only counts windows before the cancellation.
+    async fn cancelled_deal_only_counts_windows_before_cancellation() -> Result<(), ValidationError>
+    {
+        let mut chain = MockChain::new(120);
+        chain.add_deal(DealID(1), deal(100, 60, 3));
+        chain.cancel_deal(DealID(1), BlockNum(103));
+
+        let response = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await?;
+        assert_eq!(response.data.status, 1);
+        assert_eq!(response.data.num_windows, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// A deal that would end past the last block is a final answer, not a panic.
+    async fn overflowing_deal_is_invalid() {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, u64::MAX - 50, 3));
+
+        let error = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await
+        .expect_err("the deal never ends");
+        assert!(matches!(
+            error,
+            ValidationError::InvalidDealParameters { .. }
+        ));
+        assert_eq!(error.http_status(), 200);
+    }
+
+    #[tokio::test]
+    /// A deal cancelled within its first window is scored 0 out of 0, not rejected as invalid.
+    async fn deal_cancelled_before_first_window_has_no_windows() -> Result<(), ValidationError> {
+        let mut chain = MockChain::new(120);
+        chain.add_deal(DealID(1), deal(100, 60, 3));
+        chain.cancel_deal(DealID(1), BlockNum(101));
+
+        let response = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await?;
+        assert_eq!(response.data.status, 1);
+        assert_eq!(response.data.success_count, 0);
+        assert_eq!(response.data.num_windows, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// A deal that is over isn't scored until its last proof window is below the pinned block.
+    async fn deal_waits_for_finality() -> Result<(), ValidationError> {
+        let mut chain = MockChain::new(115);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+
+        let error = validate_deal_internal(
+            &chain,
+            None,
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await
+        .expect_err("last window isn't final");
+        assert_eq!(
+            error,
+            ValidationError::NotFinalized {
+                deal_id: DealID(1),
+                final_block: BlockNum(109),
+                pinned_block: BlockNum(103),
+            }
+        );
+
+        // the node's finalized block can be used instead of a fixed depth
+        chain.finalized_block_num = 110;
+        let settings = ValidationSettings {
+            finality: Finality::FinalizedTag,
+            ..Default::default()
+        };
+        let response = validate_deal_internal(&chain, None, &settings, request(DealID(1))).await?;
+        assert_eq!(response.data.pinned_block, Some(BlockNum(110)));
+        assert_eq!(
+            response.data.pinned_block_hash,
+            Some(H256::from_low_u64_be(110))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// An evidence bundle survives a JSON round trip and reproduces the live answer offline.
+    async fn evidence_bundle_reproduces_response() -> Result<(), ValidationError> {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 9, 3));
+        chain.add_proof(DealID(1), 1, BlockNum(104), Vec::new());
+        chain.add_proof(DealID(1), 2, BlockNum(107), vec![1, 2, 3]);
+
+        let bundle =
+            collect_evidence(&chain, &ValidationSettings::default(), request(DealID(1))).await?;
+        assert_eq!(bundle.windows.len(), 3);
+        assert_eq!(bundle.windows[2].proof.as_deref(), Some(&[1u8, 2, 3][..]));
+
+        let json = serde_json::to_string(&bundle).expect("bundle serializes");
+        let bundle: EvidenceBundle = serde_json::from_str(&json).expect("bundle deserializes");
+        let recomputed = bundle.verify().expect("bundle is consistent");
+        assert_eq!(
+            serde_json::to_value(&recomputed).expect("response serializes"),
+            serde_json::to_value(&bundle.response).expect("response serializes")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// A bundle whose chunk choice or cancellation doesn't fit the deal is refused rather than
+    /// re-checked on its own terms.
+    async fn inconsistent_bundles_are_rejected() -> Result<(), ValidationError> {
+        let mut chain = MockChain::new(130);
+        chain.add_deal(DealID(1), deal(100, 9, 3));
+        let bundle =
+            collect_evidence(&chain, &ValidationSettings::default(), request(DealID(1))).await?;
+        bundle.verify().expect("bundle is consistent");
+
+        let mut moved_chunk = bundle.clone();
+        moved_chunk.windows[1].chunk_offset += 1;
+        let error = moved_chunk
+            .verify()
+            .expect_err("chunk doesn't match the hash");
+        assert!(error.to_string().contains("chunk"), "{error}");
+
+        for cancellation_block in [99, 109] {
+            let mut cancelled = bundle.clone();
+            cancelled.cancellation_block = Some(BlockNum(cancellation_block));
+            let error = cancelled
+                .verify()
+                .expect_err("cancellation outside the deal");
+            assert!(error.to_string().contains("outside the deal"), "{error}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// Once a deal is past the confirmation depth its result is served from the cache.
+    async fn finalized_result_is_cached() -> Result<(), ValidationError> {
+        let cache = ResultCache::new(8, 12, None).expect("cache");
+        let mut chain = MockChain::new(200);
+        chain.add_deal(DealID(1), deal(100, 6, 3));
+        chain.add_proof(DealID(1), 0, BlockNum(101), Vec::new());
+        let first = validate_deal_internal(
+            &chain,
+            Some(&cache),
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await?;
+
+        // a chain that has forgotten the proof still gets the cached answer
+        chain.proof_blocks.clear();
+        let second = validate_deal_internal(
+            &chain,
+            Some(&cache),
+            &ValidationSettings::default(),
+            request(DealID(1)),
+        )
+        .await?;
+        assert_eq!(
+            first.data.windows.expect("windows")[0].verdict,
+            WindowVerdict::EmptyOrShort
+        );
+        assert_eq!(
+            second.data.windows.expect("windows")[0].verdict,
+            WindowVerdict::EmptyOrShort
+        );
+        Ok(())
     }
 }
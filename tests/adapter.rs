@@ -0,0 +1,29 @@
+//! Checks against a running adapter, which `cargo test` doesn't start. Run them with
+//! `cargo test --test adapter -- --ignored` once the adapter is up.
+#![cfg(feature = "client")]
+
+use banyan_shared::types::DealID;
+use chainlink_proof_validator::client::rust_chainlink_ea_api_call;
+
+fn adapter_url() -> String {
+    std::env::var("ADAPTER_URL").unwrap_or_else(|_| "http://127.0.0.1:8000/compute".to_string())
+}
+
+fn deal_id() -> DealID {
+    DealID(
+        std::env::var("DEAL_ID")
+            .map(|deal_id| deal_id.parse().expect("DEAL_ID is a number"))
+            .unwrap_or(1),
+    )
+}
+
+#[tokio::test]
+#[ignore = "needs a running adapter and a deal on the contract"]
+/// The adapter scores an existing deal, or gives a final answer about it.
+async fn api_call_test() -> Result<(), anyhow::Error> {
+    let deal_id = deal_id();
+    let response = rust_chainlink_ea_api_call(deal_id, adapter_url()).await?;
+    assert_eq!(response.data.deal_id.0, deal_id.0);
+    assert!(response.data.success_count <= response.data.num_windows);
+    Ok(())
+}